@@ -1,18 +1,14 @@
-use std::time::Duration;
-
 use sirlog::{backend, info, Configuration, Manager};
 
 #[tokio::main]
 async fn main() {
-    Configuration::set_global(Configuration::named(
-        "hello",
-        Manager::default()
-            .with_backend(backend::Os::std())
-            .spawn_tokio(),
-    ));
+    let manager = Manager::default()
+        .with_backend(backend::Os::std())
+        .spawn_tokio();
+    Configuration::set_global(Configuration::named("hello", manager.clone()));
 
     info!("Sir Log says, 'Hello, World!'");
 
-    // sirlog is asynchronous, so without yielding the main task the log message will not appear
-    tokio::time::sleep(Duration::from_millis(1)).await;
+    // sirlog is asynchronous, so wait for the message to be written before exiting
+    manager.shutdown().await;
 }
@@ -1,16 +1,116 @@
-use std::fmt::Debug;
+use std::{fmt::Debug, sync::Arc};
 
 use crate::Log;
 use async_trait::async_trait;
+use futures::io::AsyncWrite;
 
+mod file;
+mod format;
+mod json_lines;
 mod memory;
 mod os;
 
-pub use self::{memory::*, os::*};
+pub use self::{file::*, format::*, json_lines::*, memory::*, os::*};
+
+/// A writer that backends writing to any `futures::io::AsyncWrite` can own
+trait AsyncWriter: AsyncWrite + Send + Sync + Debug + Unpin + 'static {}
+
+impl<T> AsyncWriter for T where T: AsyncWrite + Send + Sync + Debug + Unpin + 'static {}
+
+/// An error returned from `Backend::process_batch`
+#[derive(Debug)]
+pub struct BatchError {
+    /// The number of entries at the start of the batch that were processed
+    /// before the failure. The entry at this index is the one that failed.
+    pub processed: usize,
+    /// The error the entry failed with
+    pub error: anyhow::Error,
+}
+
+/// Converts errors that occur before any entry in the batch is processed
+impl<E: Into<anyhow::Error>> From<E> for BatchError {
+    fn from(error: E) -> Self {
+        Self {
+            processed: 0,
+            error: error.into(),
+        }
+    }
+}
 
 /// A logging backend
 #[async_trait]
 pub trait Backend: Debug + Send + Sync {
     /// Process the log message `log`
     async fn process_log(&mut self, log: &Log) -> anyhow::Result<()>;
+
+    /// Process several log messages at once. The `Manager` batches entries
+    /// that are already queued for a backend, so backends that can write
+    /// several entries at once should override this method. By default, each
+    /// entry is passed to `process_log` in order.
+    ///
+    /// If an entry fails, the returned error records how many entries were
+    /// processed before it. The `Manager` reports the failing entry and passes
+    /// the entries after it in a new batch.
+    async fn process_batch(&mut self, logs: &[Arc<Log>]) -> Result<(), BatchError> {
+        for (processed, log) in logs.iter().enumerate() {
+            self.process_log(log)
+                .await
+                .map_err(|error| BatchError { processed, error })?;
+        }
+        Ok(())
+    }
+
+    /// Prepare the backend. This is called once when the `Manager` is
+    /// launched, before any entries are processed.
+    async fn init(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Write out any buffered entries. This is called whenever the backend's
+    /// queue becomes empty, when `ManagerHandle::flush` is called, and before
+    /// the backend is shut down.
+    async fn flush(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Finalize the backend. This is called once when the `Manager` is shut
+    /// down, after every queued entry has been processed and flushed.
+    async fn shutdown(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Creates an entry with no payload, location, template or event, timestamped
+/// now. Tests set any other fields using struct update syntax.
+#[cfg(test)]
+pub(crate) fn test_entry(level: crate::Level, process: &str, message: &str) -> Log {
+    Log {
+        level,
+        process: String::from(process),
+        message: String::from(message),
+        timestamp: chrono::Utc::now(),
+        payload: serde_json::Value::Null,
+        location: None,
+        template: None,
+        event: None,
+        inherited: Vec::new(),
+    }
+}
+
+/// A writer whose clones share one buffer, so tests can inspect what a
+/// backend wrote after handing it a clone
+#[cfg(test)]
+#[derive(Clone, Debug, Default)]
+struct SharedBuffer(Arc<std::sync::Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl std::io::Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
 }
@@ -0,0 +1,747 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime, Timelike, Utc};
+
+use super::{Backend, BatchError, Compact, Formatter};
+use crate::Log;
+
+/// The format of the timestamp appended to the name of a rotated file
+const ROTATED_TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H-%M-%S%.6fZ";
+
+/// The extensions appended to the name of a rotated file once it has been
+/// compressed. These are recognized regardless of which compression features
+/// are enabled, so that retention applies to every rotated file.
+const COMPRESSED_EXTENSIONS: [&str; 2] = [".gz", ".zst"];
+
+/// Controls when a `File` backend moves its current file aside and starts a
+/// new one
+#[derive(Default, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Rotation {
+    /// Never rotate the file
+    #[default]
+    Never,
+    /// Rotate the file before an entry would grow it beyond the provided
+    /// number of bytes
+    Size(u64),
+    /// Rotate the file when an entry's timestamp falls on a later day (UTC)
+    /// than the entries already in the file
+    Daily,
+    /// Rotate the file when an entry's timestamp falls in a later hour (UTC)
+    /// than the entries already in the file
+    Hourly,
+}
+
+impl Rotation {
+    /// Returns the start of the schedule period that `time` falls in, or
+    /// `None` if this rotation isn't scheduled
+    fn period(self, time: DateTime<Utc>) -> Option<NaiveDateTime> {
+        match self {
+            Self::Daily => time.date_naive().and_hms_opt(0, 0, 0),
+            Self::Hourly => time.date_naive().and_hms_opt(time.hour(), 0, 0),
+            Self::Never | Self::Size(_) => None,
+        }
+    }
+}
+
+/// An algorithm used by a `File` backend to compress the files it rotates
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Compression {
+    /// Compress using gzip, appending `.gz` to the rotated file's name
+    #[cfg(feature = "gzip")]
+    Gzip,
+    /// Compress using Zstandard, appending `.zst` to the rotated file's name
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    /// Compresses `source` into a new file, deleting `source` once the
+    /// compressed file is complete
+    // Without a compression feature, there are no variants to compress with.
+    #[cfg_attr(
+        not(any(feature = "gzip", feature = "zstd")),
+        allow(unused_variables, clippy::missing_const_for_fn)
+    )]
+    fn compress(self, source: &Path) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip => compress_file(
+                source,
+                ".gz",
+                |output| {
+                    Ok(flate2::write::GzEncoder::new(
+                        output,
+                        flate2::Compression::default(),
+                    ))
+                },
+                flate2::write::GzEncoder::finish,
+            ),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => compress_file(
+                source,
+                ".zst",
+                |output| zstd::Encoder::new(output, 0),
+                zstd::Encoder::finish,
+            ),
+        }
+    }
+}
+
+/// Writes `source` through the encoder created by `encoder` into a file named
+/// by appending `extension` to `source`. The output is written under a
+/// temporary name and renamed once it is complete, so that a partially
+/// compressed file is never mistaken for a rotated file.
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+fn compress_file<W: Write>(
+    source: &Path,
+    extension: &str,
+    encoder: impl FnOnce(fs::File) -> io::Result<W>,
+    finish: impl FnOnce(W) -> io::Result<fs::File>,
+) -> io::Result<()> {
+    let mut destination = source.as_os_str().to_owned();
+    destination.push(extension);
+    let mut partial = destination.clone();
+    partial.push(".partial");
+
+    let mut input = fs::File::open(source)?;
+    let mut output = encoder(fs::File::create(&partial)?)?;
+    io::copy(&mut input, &mut output)?;
+    finish(output)?.sync_all()?;
+    fs::rename(&partial, &destination)?;
+
+    match fs::remove_file(source) {
+        Err(error) if error.kind() != ErrorKind::NotFound => Err(error),
+        _ => Ok(()),
+    }
+}
+
+/// Opens a file written by a `File` backend for reading, transparently
+/// decompressing it if it was compressed after being rotated.
+///
+/// The format is detected from the file's contents, so this works regardless
+/// of the file's name. Reading a compressed file requires the feature of the
+/// algorithm it was compressed with.
+pub fn open_segment<P: AsRef<Path>>(path: P) -> anyhow::Result<Box<dyn BufRead + Send>> {
+    let mut reader = BufReader::new(fs::File::open(path)?);
+    let magic = reader.fill_buf()?;
+    if magic.starts_with(&[0x1f, 0x8b]) {
+        #[cfg(feature = "gzip")]
+        return Ok(Box::new(BufReader::new(
+            flate2::bufread::MultiGzDecoder::new(reader),
+        )));
+        #[cfg(not(feature = "gzip"))]
+        anyhow::bail!("reading gzip-compressed files requires the gzip feature");
+    } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        #[cfg(feature = "zstd")]
+        return Ok(Box::new(BufReader::new(zstd::Decoder::with_buffer(
+            reader,
+        )?)));
+        #[cfg(not(feature = "zstd"))]
+        anyhow::bail!("reading zstd-compressed files requires the zstd feature");
+    }
+
+    Ok(Box::new(reader))
+}
+
+/// A backend that appends entries to a file. Entries are formatted using
+/// `Compact` unless another `Formatter` is provided.
+///
+/// When the file is rotated, it is renamed by appending the time of the
+/// rotation to its name (`app.log` becomes
+/// `app.log.2021-01-02T03-04-05.000000Z`), and a new file is started. If the
+/// file is moved or deleted by another program, such as `logrotate`, a new
+/// file is created at the original path before the next entries are written.
+///
+/// Rotated files can optionally be compressed using `with_compression()`.
+/// Compression happens on a background thread, and `open_segment()` can read
+/// rotated files whether or not they have been compressed.
+///
+/// Writes are performed using `std::fs`, which works with any async executor
+/// but blocks the backend's task while writing.
+#[derive(Debug)]
+pub struct File {
+    path: PathBuf,
+    rotation: Rotation,
+    max_files: Option<usize>,
+    max_age: Option<Duration>,
+    formatter: Box<dyn Formatter>,
+    compression: Option<Compression>,
+    compressing: Vec<JoinHandle<io::Result<()>>>,
+    current: Option<OpenFile>,
+}
+
+/// The file a `File` backend is currently appending to
+#[derive(Debug)]
+struct OpenFile {
+    writer: BufWriter<fs::File>,
+    size: u64,
+    /// The schedule period of the entries in the file, if known
+    period: Option<NaiveDateTime>,
+    identity: Option<FileIdentity>,
+}
+
+impl File {
+    /// Create a `File` backend that appends to `path`, creating it and any
+    /// missing parent directories if needed. The file is never rotated by
+    /// default.
+    #[must_use]
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            rotation: Rotation::default(),
+            max_files: None,
+            max_age: None,
+            formatter: Box::new(Compact::default()),
+            compression: None,
+            compressing: Vec::new(),
+            current: None,
+        }
+    }
+
+    /// Builder-style method to set when the file is rotated
+    #[must_use]
+    pub const fn with_rotation(mut self, rotation: Rotation) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    /// Builder-style method to limit how many rotated files are kept. The
+    /// oldest rotated files beyond `max_files` are deleted.
+    #[must_use]
+    pub const fn with_max_files(mut self, max_files: usize) -> Self {
+        self.max_files = Some(max_files);
+        self
+    }
+
+    /// Builder-style method to delete rotated files that were rotated more
+    /// than `max_age` ago
+    #[must_use]
+    pub const fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Builder-style method to compress files once they have been rotated.
+    /// Rotated files are not compressed by default.
+    #[must_use]
+    pub const fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Builder-style method to set the `Formatter` entries are written with
+    #[must_use]
+    pub fn with_formatter<F: Formatter + 'static>(mut self, formatter: F) -> Self {
+        self.formatter = Box::new(formatter);
+        self
+    }
+
+    /// Returns the path entries are appended to
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the paths of the rotated files that currently exist, oldest
+    /// first. While a rotated file is being compressed, both the original and
+    /// the compressed file may be returned.
+    pub fn rotated_files(&self) -> anyhow::Result<Vec<PathBuf>> {
+        Ok(self.rotated()?.into_iter().map(|(_, path)| path).collect())
+    }
+
+    fn open(&mut self) -> anyhow::Result<&mut OpenFile> {
+        if self.current.is_none() {
+            if let Some(parent) = self.path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            let metadata = file.metadata()?;
+            let period = if metadata.len() > 0 {
+                self.rotation
+                    .period(DateTime::<Utc>::from(metadata.modified()?))
+            } else {
+                None
+            };
+            self.current = Some(OpenFile {
+                writer: BufWriter::new(file),
+                size: metadata.len(),
+                period,
+                identity: FileIdentity::of(&metadata),
+            });
+        }
+
+        Ok(self.current.as_mut().expect("file was just opened"))
+    }
+
+    /// Closes the current file if it is no longer at `path`, so that the next
+    /// entry is written to a new file
+    fn reopen_if_moved(&mut self) -> anyhow::Result<()> {
+        if let Some(current) = &mut self.current {
+            let moved = match fs::metadata(&self.path) {
+                Ok(metadata) => FileIdentity::of(&metadata) != current.identity,
+                Err(error) if error.kind() == ErrorKind::NotFound => true,
+                Err(error) => return Err(error.into()),
+            };
+            if moved {
+                // Anything still buffered belongs at the end of the file that
+                // was moved.
+                current.writer.flush()?;
+                self.current = None;
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, log: &Log) -> anyhow::Result<()> {
+        let mut line = String::new();
+        self.formatter.format(log, &mut line);
+        let period = self.rotation.period(log.timestamp);
+        let rotation = self.rotation;
+        let current = self.open()?;
+        let due = match rotation {
+            Rotation::Never => false,
+            Rotation::Size(max_size) => {
+                current.size > 0 && current.size + line.len() as u64 > max_size
+            }
+            Rotation::Daily | Rotation::Hourly => {
+                current.period.is_some() && period > current.period
+            }
+        };
+        if due {
+            self.rotate()?;
+        }
+
+        let current = self.open()?;
+        current.writer.write_all(line.as_bytes())?;
+        current.size += line.len() as u64;
+        if current.period.is_none() {
+            current.period = period;
+        }
+        Ok(())
+    }
+
+    /// Renames the current file using the current time, and deletes any
+    /// rotated files that are no longer retained
+    fn rotate(&mut self) -> anyhow::Result<()> {
+        if let Some(mut current) = self.current.take() {
+            current.writer.flush()?;
+        }
+
+        let mut rotated_at = Utc::now();
+        let mut destination = self.rotated_path(rotated_at);
+        while destination.exists() {
+            rotated_at += chrono::Duration::microseconds(1);
+            destination = self.rotated_path(rotated_at);
+        }
+        fs::rename(&self.path, &destination)?;
+        self.compress(destination);
+
+        self.prune()
+    }
+
+    /// Compresses the rotated file at `path` on a background thread, if
+    /// compression is enabled
+    fn compress(&mut self, path: PathBuf) {
+        if let Some(compression) = self.compression {
+            let handle = std::thread::Builder::new()
+                .name(String::from("sirlog-compression"))
+                .spawn(move || compression.compress(&path));
+            match handle {
+                Ok(handle) => self.compressing.push(handle),
+                Err(error) => self
+                    .compressing
+                    .push(std::thread::spawn(move || Err(error))),
+            }
+        }
+    }
+
+    /// Reports the result of the background compressions that have finished,
+    /// or of every background compression if `wait` is true
+    fn finish_compressing(&mut self, wait: bool) -> anyhow::Result<()> {
+        let mut result = Ok(());
+        let mut still_running = Vec::new();
+        for handle in self.compressing.drain(..) {
+            if wait || handle.is_finished() {
+                let error = match handle.join() {
+                    Ok(Ok(())) => continue,
+                    Ok(Err(error)) => anyhow::Error::from(error).context("compression failed"),
+                    Err(_) => anyhow::anyhow!("compression thread panicked"),
+                };
+                if result.is_ok() {
+                    result = Err(error);
+                }
+            } else {
+                still_running.push(handle);
+            }
+        }
+        self.compressing = still_running;
+        result
+    }
+
+    fn rotated_path(&self, rotated_at: DateTime<Utc>) -> PathBuf {
+        let mut name = self.file_name().to_string();
+        name.push('.');
+        name.push_str(&rotated_at.format(ROTATED_TIMESTAMP_FORMAT).to_string());
+        self.path.with_file_name(name)
+    }
+
+    fn file_name(&self) -> &str {
+        self.path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default()
+    }
+
+    /// Returns the rotated files and the time each was rotated, oldest first
+    fn rotated(&self) -> anyhow::Result<Vec<(NaiveDateTime, PathBuf)>> {
+        let directory = match self.path.parent() {
+            Some(parent) if parent != Path::new("") => parent,
+            _ => Path::new("."),
+        };
+        let prefix = format!("{}.", self.file_name());
+
+        let mut rotated = Vec::new();
+        for entry in fs::read_dir(directory)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let rotated_at = name
+                .to_str()
+                .and_then(|name| name.strip_prefix(&prefix))
+                .map(|suffix| {
+                    COMPRESSED_EXTENSIONS
+                        .iter()
+                        .find_map(|extension| suffix.strip_suffix(extension))
+                        .unwrap_or(suffix)
+                })
+                .and_then(|timestamp| {
+                    NaiveDateTime::parse_from_str(timestamp, ROTATED_TIMESTAMP_FORMAT).ok()
+                });
+            if let Some(rotated_at) = rotated_at {
+                rotated.push((rotated_at, entry.path()));
+            }
+        }
+        rotated.sort();
+        Ok(rotated)
+    }
+
+    /// Deletes the rotated files beyond `max_files` or older than `max_age`
+    fn prune(&self) -> anyhow::Result<()> {
+        if self.max_files.is_none() && self.max_age.is_none() {
+            return Ok(());
+        }
+
+        let rotated = self.rotated()?;
+        // A file being compressed exists under two names, which are counted
+        // once.
+        let mut rotations = rotated
+            .iter()
+            .map(|(rotated_at, _)| *rotated_at)
+            .collect::<Vec<_>>();
+        rotations.dedup();
+        let excess = self
+            .max_files
+            .map_or(0, |max_files| rotations.len().saturating_sub(max_files));
+        let oldest_retained = self.max_age.and_then(|max_age| {
+            let max_age = chrono::Duration::from_std(max_age).ok()?;
+            Utc::now().naive_utc().checked_sub_signed(max_age)
+        });
+        for (rotated_at, path) in rotated {
+            let expired = oldest_retained.is_some_and(|oldest| rotated_at < oldest);
+            if rotations[..excess].contains(&rotated_at) || expired {
+                match fs::remove_file(path) {
+                    Err(error) if error.kind() != ErrorKind::NotFound => return Err(error.into()),
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Backend for File {
+    async fn process_log(&mut self, log: &Log) -> anyhow::Result<()> {
+        self.reopen_if_moved()?;
+        self.write(log)
+    }
+
+    async fn process_batch(&mut self, logs: &[std::sync::Arc<Log>]) -> Result<(), BatchError> {
+        self.reopen_if_moved()?;
+        for (processed, log) in logs.iter().enumerate() {
+            self.write(log)
+                .map_err(|error| BatchError { processed, error })?;
+        }
+        Ok(())
+    }
+
+    async fn init(&mut self) -> anyhow::Result<()> {
+        self.open()?;
+        self.prune()?;
+
+        // Finish compressing any files that were rotated without being
+        // compressed, such as when the process exited during compression.
+        if self.compression.is_some() {
+            for (_, path) in self.rotated()? {
+                let compressed = path.to_str().is_some_and(|path| {
+                    COMPRESSED_EXTENSIONS
+                        .iter()
+                        .any(|extension| path.ends_with(extension))
+                });
+                if !compressed {
+                    self.compress(path);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> anyhow::Result<()> {
+        if let Some(current) = &mut self.current {
+            current.writer.flush()?;
+        }
+        self.finish_compressing(false)
+    }
+
+    async fn shutdown(&mut self) -> anyhow::Result<()> {
+        if let Some(mut current) = self.current.take() {
+            current.writer.flush()?;
+        }
+        self.finish_compressing(true)
+    }
+}
+
+/// Identifies the file a path refers to, so that a file being replaced can be
+/// detected
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct FileIdentity {
+    device: u64,
+    inode: u64,
+}
+
+impl FileIdentity {
+    #[cfg(unix)]
+    #[allow(clippy::unnecessary_wraps)] // Other platforms can't identify files.
+    fn of(metadata: &fs::Metadata) -> Option<Self> {
+        use std::os::unix::fs::MetadataExt;
+
+        Some(Self {
+            device: metadata.dev(),
+            inode: metadata.ino(),
+        })
+    }
+
+    /// Only a file being removed from its path can be detected on this
+    /// platform
+    #[cfg(not(unix))]
+    fn of(_metadata: &fs::Metadata) -> Option<Self> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::{backend::test_entry, Level};
+
+    fn test_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("sirlog-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn entry(message: &str, timestamp: DateTime<Utc>) -> Log {
+        Log {
+            timestamp,
+            ..test_entry(Level::Info, "file_test", message)
+        }
+    }
+
+    fn lines(path: &Path) -> Vec<String> {
+        open_segment(path)
+            .unwrap()
+            .lines()
+            .map(|line| line.unwrap().rsplit(": ").next().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn size_rotation_test() -> anyhow::Result<()> {
+        let directory = test_directory("size_rotation_test");
+        let mut file = File::new(directory.join("app.log"))
+            .with_rotation(Rotation::Size(100))
+            .with_max_files(2);
+
+        futures::executor::block_on(async {
+            file.init().await?;
+            // Each line is 49 bytes long.
+            let timestamp = Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap();
+            for message in ["A", "B", "C", "D"] {
+                file.process_log(&entry(message, timestamp)).await?;
+                file.process_log(&entry(message, timestamp)).await?;
+            }
+            file.shutdown().await
+        })?;
+
+        // Each file holds two entries, and only the two newest rotated files
+        // are retained.
+        let rotated = file.rotated_files()?;
+        assert_eq!(rotated.len(), 2);
+        assert_eq!(lines(&rotated[0]), vec!["B", "B"]);
+        assert_eq!(lines(&rotated[1]), vec!["C", "C"]);
+        assert_eq!(lines(file.path()), vec!["D", "D"]);
+
+        fs::remove_dir_all(directory)?;
+        Ok(())
+    }
+
+    #[test]
+    fn schedule_rotation_test() -> anyhow::Result<()> {
+        let directory = test_directory("schedule_rotation_test");
+        let mut file = File::new(directory.join("app.log")).with_rotation(Rotation::Hourly);
+
+        futures::executor::block_on(async {
+            file.process_log(&entry(
+                "A",
+                Utc.with_ymd_and_hms(2021, 1, 1, 1, 0, 0).unwrap(),
+            ))
+            .await?;
+            file.process_log(&entry(
+                "B",
+                Utc.with_ymd_and_hms(2021, 1, 1, 1, 59, 59).unwrap(),
+            ))
+            .await?;
+            file.process_log(&entry(
+                "C",
+                Utc.with_ymd_and_hms(2021, 1, 1, 2, 0, 0).unwrap(),
+            ))
+            .await?;
+            file.shutdown().await
+        })?;
+
+        let rotated = file.rotated_files()?;
+        assert_eq!(rotated.len(), 1);
+        assert_eq!(lines(&rotated[0]), vec!["A", "B"]);
+        assert_eq!(lines(file.path()), vec!["C"]);
+
+        // An existing file last written to on a previous day is rotated before
+        // the first new entry is written.
+        let mut file = File::new(file.path()).with_rotation(Rotation::Daily);
+        fs::File::options()
+            .write(true)
+            .open(file.path())?
+            .set_modified((Utc::now() - chrono::Duration::days(2)).into())?;
+        futures::executor::block_on(async {
+            file.process_log(&entry("D", Utc::now())).await?;
+            file.shutdown().await
+        })?;
+        assert_eq!(file.rotated_files()?.len(), 2);
+        assert_eq!(lines(file.path()), vec!["D"]);
+
+        fs::remove_dir_all(directory)?;
+        Ok(())
+    }
+
+    #[test]
+    fn max_age_test() -> anyhow::Result<()> {
+        let directory = test_directory("max_age_test");
+        fs::create_dir_all(&directory)?;
+        let file = File::new(directory.join("app.log")).with_max_age(Duration::from_secs(60 * 60));
+        let expired = file.rotated_path(Utc::now() - chrono::Duration::hours(2));
+        let retained = file.rotated_path(Utc::now() - chrono::Duration::minutes(30));
+        let unrelated = directory.join("app.log.old");
+        for path in [&expired, &retained, &unrelated] {
+            fs::write(path, "")?;
+        }
+
+        let mut file = file;
+        futures::executor::block_on(file.init())?;
+
+        assert_eq!(file.rotated_files()?, vec![retained]);
+        assert!(unrelated.exists());
+
+        fs::remove_dir_all(directory)?;
+        Ok(())
+    }
+
+    #[test]
+    fn reopen_test() -> anyhow::Result<()> {
+        let directory = test_directory("reopen_test");
+        let mut file = File::new(directory.join("app.log"));
+        let moved = directory.join("moved.log");
+
+        futures::executor::block_on(async {
+            file.process_log(&entry("A", Utc::now())).await?;
+            file.flush().await?;
+            fs::rename(file.path(), &moved)?;
+            file.process_log(&entry("B", Utc::now())).await?;
+            file.shutdown().await
+        })?;
+
+        assert_eq!(lines(&moved), vec!["A"]);
+        assert_eq!(lines(file.path()), vec!["B"]);
+
+        fs::remove_dir_all(directory)?;
+        Ok(())
+    }
+
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    fn compression_test(
+        name: &str,
+        compression: Compression,
+        extension: &str,
+    ) -> anyhow::Result<()> {
+        let directory = test_directory(name);
+        let mut file = File::new(directory.join("app.log"))
+            .with_rotation(Rotation::Size(100))
+            .with_compression(compression);
+
+        futures::executor::block_on(async {
+            file.init().await?;
+            let timestamp = Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap();
+            for message in ["A", "B", "C"] {
+                file.process_log(&entry(message, timestamp)).await?;
+                file.process_log(&entry(message, timestamp)).await?;
+            }
+            // Shutting down waits for the background compression to finish.
+            file.shutdown().await
+        })?;
+
+        let rotated = file.rotated_files()?;
+        assert_eq!(rotated.len(), 2);
+        assert!(rotated
+            .iter()
+            .all(|path| path.to_str().unwrap().ends_with(extension)));
+        assert_eq!(lines(&rotated[0]), vec!["A", "A"]);
+        assert_eq!(lines(&rotated[1]), vec!["B", "B"]);
+        assert_eq!(lines(file.path()), vec!["C", "C"]);
+        // Only the current file and the compressed files remain.
+        assert_eq!(fs::read_dir(&directory)?.count(), 3);
+
+        fs::remove_dir_all(directory)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzip_test() -> anyhow::Result<()> {
+        compression_test("gzip_test", Compression::Gzip, ".gz")
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_test() -> anyhow::Result<()> {
+        compression_test("zstd_test", Compression::Zstd, ".zst")
+    }
+}
@@ -0,0 +1,574 @@
+use std::{
+    ffi::OsString,
+    fmt::{Debug, Write},
+};
+
+use chrono::format::{Item, StrftimeItems};
+use serde_json::Value;
+
+use crate::{Level, Log};
+
+/// Formats entries as text for backends that write lines of text, such as
+/// `Os` and `File`
+pub trait Formatter: Debug + Send + Sync {
+    /// Appends `log` to `output`, including a trailing newline
+    fn format(&self, log: &Log, output: &mut String);
+
+    /// Appends `log` to `output` like `format()`, using ANSI escape codes to
+    /// color it. This is used when writing to a terminal. By default, this
+    /// calls `format()`, writing `log` without color.
+    fn format_colored(&self, log: &Log, output: &mut String) {
+        self.format(log, output);
+    }
+}
+
+/// Controls whether a backend writes colored output
+#[derive(Default, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ColorChoice {
+    /// Color output written to a terminal. Setting the `NO_COLOR` environment
+    /// variable disables color, and setting `CLICOLOR_FORCE` enables color
+    /// even when not writing to a terminal. `NO_COLOR` takes precedence.
+    #[default]
+    Auto,
+    /// Always color output
+    Always,
+    /// Never color output
+    Never,
+}
+
+impl ColorChoice {
+    /// Returns whether output written to a destination should be colored.
+    /// `is_terminal` indicates whether the destination is a terminal.
+    #[must_use]
+    pub fn enabled(self, is_terminal: bool) -> bool {
+        self.resolve(
+            is_terminal,
+            std::env::var_os("NO_COLOR"),
+            std::env::var_os("CLICOLOR_FORCE"),
+        )
+    }
+
+    fn resolve(
+        self,
+        is_terminal: bool,
+        no_color: Option<OsString>,
+        clicolor_force: Option<OsString>,
+    ) -> bool {
+        let is_set = |variable: Option<OsString>| variable.is_some_and(|value| !value.is_empty());
+        match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::Auto if is_set(no_color) => false,
+            Self::Auto => {
+                is_terminal || is_set(clicolor_force.filter(|value| value.as_os_str() != "0"))
+            }
+        }
+    }
+}
+
+/// The ANSI escape codes the built-in formatters color their output with. When
+/// disabled, each code is empty.
+#[derive(Clone, Copy, Debug)]
+struct Palette {
+    enabled: bool,
+}
+
+impl Palette {
+    const PLAIN: Self = Self { enabled: false };
+    const ANSI: Self = Self { enabled: true };
+
+    const fn code(self, code: &'static str) -> &'static str {
+        if self.enabled {
+            code
+        } else {
+            ""
+        }
+    }
+
+    const fn level(self, level: Level) -> &'static str {
+        self.code(match level {
+            Level::Trace => "\x1b[35m",
+            Level::Debug => "\x1b[34m",
+            Level::Info => "\x1b[32m",
+            Level::Warning => "\x1b[33m",
+            Level::Error => "\x1b[1;31m",
+        })
+    }
+
+    const fn dim(self) -> &'static str {
+        self.code("\x1b[2m")
+    }
+
+    const fn key(self) -> &'static str {
+        self.code("\x1b[36m")
+    }
+
+    const fn reset(self) -> &'static str {
+        self.code("\x1b[0m")
+    }
+}
+
+/// The default format: one line containing the level, timestamp, process and
+/// message of each entry, such as
+/// `INFO  [2021-01-02T03:04:05+00:00] [app]: Hello`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Compact {
+    show_location: bool,
+}
+
+impl Compact {
+    /// Builder-style method to control whether each entry's `Log::location`
+    /// is written after its message. Locations are not written by default.
+    #[must_use]
+    pub const fn with_location(mut self, show_location: bool) -> Self {
+        self.show_location = show_location;
+        self
+    }
+
+    fn write(self, log: &Log, output: &mut String, palette: Palette) {
+        let _ = write!(
+            output,
+            "{}{}{} {}[{}]{} [{}]: {}",
+            palette.level(log.level),
+            fixed_width_level(log.level),
+            palette.reset(),
+            palette.dim(),
+            log.timestamp.to_rfc3339(),
+            palette.reset(),
+            log.process,
+            log.message,
+        );
+        if let (true, Some(location)) = (self.show_location, &log.location) {
+            let _ = write!(output, " {}({location}){}", palette.dim(), palette.reset());
+        }
+        output.push('\n');
+    }
+}
+
+impl Formatter for Compact {
+    fn format(&self, log: &Log, output: &mut String) {
+        self.write(log, output, Palette::PLAIN);
+    }
+
+    fn format_colored(&self, log: &Log, output: &mut String) {
+        self.write(log, output, Palette::ANSI);
+    }
+}
+
+/// The `Compact` format, followed by the location of each entry and each field
+/// of its `payload` on their own indented lines
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Verbose;
+
+impl Verbose {
+    fn write(log: &Log, output: &mut String, palette: Palette) {
+        Compact::default().write(log, output, palette);
+        if let Some(location) = &log.location {
+            let _ = writeln!(
+                output,
+                "    {}at {location} ({}){}",
+                palette.dim(),
+                location.module_path,
+                palette.reset()
+            );
+        }
+        match &log.payload {
+            Value::Null => {}
+            Value::Object(fields) => {
+                for (key, value) in fields {
+                    let _ = writeln!(
+                        output,
+                        "    {}{key}{}: {value}",
+                        palette.key(),
+                        palette.reset()
+                    );
+                }
+            }
+            payload => {
+                let _ = writeln!(
+                    output,
+                    "    {}payload{}: {payload}",
+                    palette.key(),
+                    palette.reset()
+                );
+            }
+        }
+    }
+}
+
+impl Formatter for Verbose {
+    fn format(&self, log: &Log, output: &mut String) {
+        Self::write(log, output, Palette::PLAIN);
+    }
+
+    fn format_colored(&self, log: &Log, output: &mut String) {
+        Self::write(log, output, Palette::ANSI);
+    }
+}
+
+/// The logfmt format: one line of `key=value` pairs per entry, followed by
+/// each field of its `payload`, such as
+/// `ts=2021-01-02T03:04:05+00:00 level=info process=app msg="Hello there" user=1`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Logfmt;
+
+impl Logfmt {
+    fn write(log: &Log, output: &mut String, palette: Palette) {
+        let _ = write!(
+            output,
+            "{}ts={}{} {}level={}{}{}",
+            palette.dim(),
+            log.timestamp.to_rfc3339(),
+            palette.reset(),
+            palette.key(),
+            palette.level(log.level),
+            level_name(log.level).to_lowercase(),
+            palette.reset(),
+        );
+        write_logfmt_pair(output, palette, "process", &log.process);
+        write_logfmt_pair(output, palette, "msg", &log.message);
+        if let Some(location) = &log.location {
+            write_logfmt_pair(output, palette, "location", &location.to_string());
+        }
+        match &log.payload {
+            Value::Null => {}
+            Value::Object(fields) => {
+                for (key, value) in fields {
+                    write_logfmt_pair(output, palette, key, &value_text(value));
+                }
+            }
+            payload => write_logfmt_pair(output, palette, "payload", &payload.to_string()),
+        }
+        output.push('\n');
+    }
+}
+
+impl Formatter for Logfmt {
+    fn format(&self, log: &Log, output: &mut String) {
+        Self::write(log, output, Palette::PLAIN);
+    }
+
+    fn format_colored(&self, log: &Log, output: &mut String) {
+        Self::write(log, output, Palette::ANSI);
+    }
+}
+
+/// Appends ` key=value` to `output`, quoting `value` if needed
+fn write_logfmt_pair(output: &mut String, palette: Palette, key: &str, value: &str) {
+    output.push(' ');
+    output.push_str(palette.key());
+    output.push_str(key);
+    output.push('=');
+    output.push_str(palette.reset());
+    let needs_quotes = value.is_empty()
+        || value
+            .chars()
+            .any(|ch| ch.is_whitespace() || ch.is_control() || ch == '=' || ch == '"');
+    if needs_quotes {
+        output.push('"');
+        for ch in value.chars() {
+            match ch {
+                '"' => output.push_str("\\\""),
+                '\\' => output.push_str("\\\\"),
+                '\n' => output.push_str("\\n"),
+                '\r' => output.push_str("\\r"),
+                '\t' => output.push_str("\\t"),
+                ch => output.push(ch),
+            }
+        }
+        output.push('"');
+    } else {
+        output.push_str(value);
+    }
+}
+
+/// A format described by a template string, such as
+/// `"{timestamp:%H:%M:%S} {level} {message} {payload}"`.
+///
+/// The following placeholders are supported:
+///
+/// - `{timestamp}`: the entry's timestamp in RFC 3339 format. A `chrono`
+///   format string can be provided after a colon, such as
+///   `{timestamp:%Y-%m-%d %H:%M:%S}`.
+/// - `{level}`: the entry's level, such as `INFO`.
+/// - `{process}`: the entry's process.
+/// - `{message}`: the entry's message.
+/// - `{payload}`: the entry's payload as JSON, or nothing if it is null.
+/// - `{payload.key}`: the field `key` of the entry's payload, or nothing if it
+///   isn't present. Strings are written without quotes.
+/// - `{location}`: the entry's location, such as `src/main.rs:1:2`, or nothing
+///   if it isn't known.
+///
+/// `{{` and `}}` write a literal brace. A newline is written after each entry.
+#[derive(Clone, Debug)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Literal(String),
+    Timestamp(Option<String>),
+    Level,
+    Process,
+    Message,
+    Payload,
+    PayloadField(String),
+    Location,
+}
+
+impl Template {
+    /// Parses `template`, returning an error if it contains an unknown or
+    /// unterminated placeholder, or an invalid timestamp format
+    pub fn new(template: &str) -> anyhow::Result<Self> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let remaining = chars.as_str();
+                    let end = remaining
+                        .find('}')
+                        .ok_or_else(|| anyhow::anyhow!("unterminated placeholder in template"))?;
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::parse(&remaining[..end])?);
+                    chars = remaining[end + 1..].chars();
+                }
+                '}' => anyhow::bail!("unmatched `}}` in template; use `}}}}` for a literal brace"),
+                ch => literal.push(ch),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Self { segments })
+    }
+}
+
+impl Segment {
+    fn parse(placeholder: &str) -> anyhow::Result<Self> {
+        let (name, spec) = match placeholder.split_once(':') {
+            Some((name, spec)) => (name, Some(spec)),
+            None => (placeholder, None),
+        };
+        let segment = match (name, spec) {
+            ("timestamp", Some(spec)) => {
+                if StrftimeItems::new(spec).any(|item| item == Item::Error) {
+                    anyhow::bail!("invalid timestamp format in template: {spec}");
+                }
+                Self::Timestamp(Some(spec.to_string()))
+            }
+            ("timestamp", None) => Self::Timestamp(None),
+            ("level", None) => Self::Level,
+            ("process", None) => Self::Process,
+            ("message", None) => Self::Message,
+            ("payload", None) => Self::Payload,
+            ("location", None) => Self::Location,
+            (name, None) if name.starts_with("payload.") => {
+                Self::PayloadField(name["payload.".len()..].to_string())
+            }
+            _ => anyhow::bail!("unknown placeholder in template: {{{placeholder}}}"),
+        };
+        Ok(segment)
+    }
+}
+
+impl Template {
+    fn write(&self, log: &Log, output: &mut String, palette: Palette) {
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => output.push_str(literal),
+                Segment::Timestamp(None) => {
+                    let _ = write!(
+                        output,
+                        "{}{}{}",
+                        palette.dim(),
+                        log.timestamp.to_rfc3339(),
+                        palette.reset()
+                    );
+                }
+                Segment::Timestamp(Some(format)) => {
+                    let _ = write!(
+                        output,
+                        "{}{}{}",
+                        palette.dim(),
+                        log.timestamp.format(format),
+                        palette.reset()
+                    );
+                }
+                Segment::Level => {
+                    let _ = write!(
+                        output,
+                        "{}{}{}",
+                        palette.level(log.level),
+                        level_name(log.level),
+                        palette.reset()
+                    );
+                }
+                Segment::Process => output.push_str(&log.process),
+                Segment::Message => output.push_str(&log.message),
+                Segment::Payload => {
+                    if !log.payload.is_null() {
+                        let _ = write!(output, "{}", log.payload);
+                    }
+                }
+                Segment::PayloadField(key) => {
+                    if let Some(value) = log.payload.get(key) {
+                        output.push_str(&value_text(value));
+                    }
+                }
+                Segment::Location => {
+                    if let Some(location) = &log.location {
+                        let _ = write!(output, "{location}");
+                    }
+                }
+            }
+        }
+        output.push('\n');
+    }
+}
+
+impl Formatter for Template {
+    fn format(&self, log: &Log, output: &mut String) {
+        self.write(log, output, Palette::PLAIN);
+    }
+
+    fn format_colored(&self, log: &Log, output: &mut String) {
+        self.write(log, output, Palette::ANSI);
+    }
+}
+
+/// Returns `value` as text, without quotes if it is a string
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+const fn level_name(level: Level) -> &'static str {
+    match level {
+        Level::Trace => "TRACE",
+        Level::Debug => "DEBUG",
+        Level::Info => "INFO",
+        Level::Warning => "WARN",
+        Level::Error => "ERROR",
+    }
+}
+
+const fn fixed_width_level(level: Level) -> &'static str {
+    match level {
+        Level::Trace => "TRACE",
+        Level::Debug => "DEBUG",
+        Level::Info => "INFO ",
+        Level::Warning => "WARN ",
+        Level::Error => "ERROR",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+    use crate::{backend::test_entry, Location};
+
+    fn entry() -> Log {
+        Log {
+            timestamp: Utc.with_ymd_and_hms(2021, 1, 2, 3, 4, 5).unwrap(),
+            payload: serde_json::json!({ "user": 1, "name": "a \"b\"" }),
+            location: Some(Location::new("src/main.rs", 1, 2, "app")),
+            ..test_entry(Level::Warning, "format_test", "Hello there")
+        }
+    }
+
+    fn format<F: Formatter>(formatter: &F) -> String {
+        let mut output = String::new();
+        formatter.format(&entry(), &mut output);
+        output
+    }
+
+    #[test]
+    fn builtin_formatters_test() {
+        assert_eq!(
+            format(&Compact::default()),
+            "WARN  [2021-01-02T03:04:05+00:00] [format_test]: Hello there\n"
+        );
+        assert_eq!(
+            format(&Compact::default().with_location(true)),
+            "WARN  [2021-01-02T03:04:05+00:00] [format_test]: Hello there (src/main.rs:1:2)\n"
+        );
+        assert_eq!(
+            format(&Verbose),
+            "WARN  [2021-01-02T03:04:05+00:00] [format_test]: Hello there\n    at \
+             src/main.rs:1:2 (app)\n    name: \"a \\\"b\\\"\"\n    user: 1\n"
+        );
+        assert_eq!(
+            format(&Logfmt),
+            "ts=2021-01-02T03:04:05+00:00 level=warn process=format_test msg=\"Hello there\" \
+             location=src/main.rs:1:2 name=\"a \\\"b\\\"\" user=1\n"
+        );
+    }
+
+    #[test]
+    fn color_test() -> anyhow::Result<()> {
+        let mut output = String::new();
+        Verbose.format_colored(&entry(), &mut output);
+        assert_eq!(
+            output,
+            "\x1b[33mWARN \x1b[0m \x1b[2m[2021-01-02T03:04:05+00:00]\x1b[0m [format_test]: \
+             Hello there\n    \x1b[2mat src/main.rs:1:2 (app)\x1b[0m\n    \x1b[36mname\x1b[0m: \
+             \"a \\\"b\\\"\"\n    \x1b[36muser\x1b[0m: 1\n"
+        );
+
+        let mut output = String::new();
+        Template::new("{timestamp:%H} {level} {message}")?.format_colored(&entry(), &mut output);
+        assert_eq!(output, "\x1b[2m03\x1b[0m \x1b[33mWARN\x1b[0m Hello there\n");
+        Ok(())
+    }
+
+    #[test]
+    fn color_choice_test() {
+        let set = || Some(OsString::from("1"));
+        assert!(ColorChoice::Auto.resolve(true, None, None));
+        assert!(!ColorChoice::Auto.resolve(false, None, None));
+        assert!(!ColorChoice::Auto.resolve(true, set(), None));
+        assert!(ColorChoice::Auto.resolve(true, Some(OsString::new()), None));
+        assert!(ColorChoice::Auto.resolve(false, None, set()));
+        assert!(!ColorChoice::Auto.resolve(false, None, Some(OsString::from("0"))));
+        assert!(!ColorChoice::Auto.resolve(false, set(), set()));
+        assert!(ColorChoice::Always.resolve(false, set(), None));
+        assert!(!ColorChoice::Never.resolve(true, None, set()));
+    }
+
+    #[test]
+    fn template_test() -> anyhow::Result<()> {
+        let template = Template::new(
+            "{timestamp:%H:%M:%S} {level} {message} {payload} {{{payload.name}}} {location}",
+        )?;
+        assert_eq!(
+            format(&template),
+            "03:04:05 WARN Hello there {\"name\":\"a \\\"b\\\"\",\"user\":1} {a \"b\"} \
+             src/main.rs:1:2\n"
+        );
+
+        assert!(Template::new("{unknown}").is_err());
+        assert!(Template::new("{message").is_err());
+        assert!(Template::new("message}").is_err());
+        assert!(Template::new("{message:%H}").is_err());
+        assert!(Template::new("{timestamp:%Q}").is_err());
+        Ok(())
+    }
+}
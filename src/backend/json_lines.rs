@@ -0,0 +1,274 @@
+use std::{borrow::Cow, fmt::Write};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::io::{AsyncWrite, AsyncWriteExt};
+use serde_json::{Map, Value};
+
+use super::{AsyncWriter, Backend, BatchError};
+use crate::Log;
+
+/// The format `JsonLines` writes each entry's timestamp in
+#[derive(Default, Clone, Debug, Eq, PartialEq)]
+pub enum TimestampFormat {
+    /// An RFC 3339 string, such as `2021-01-02T03:04:05.123456789Z`. This is
+    /// how `Log` serializes its timestamp.
+    #[default]
+    Rfc3339,
+    /// The number of seconds since the Unix epoch
+    UnixSeconds,
+    /// The number of milliseconds since the Unix epoch
+    UnixMillis,
+    /// The number of microseconds since the Unix epoch
+    UnixMicros,
+    /// A string formatted using a `chrono` format string, such as
+    /// `"%Y-%m-%d %H:%M:%S%.3f"`
+    Custom(Cow<'static, str>),
+}
+
+impl TimestampFormat {
+    fn format(&self, timestamp: DateTime<Utc>) -> anyhow::Result<Value> {
+        Ok(match self {
+            Self::Rfc3339 => serde_json::to_value(timestamp)?,
+            Self::UnixSeconds => Value::from(timestamp.timestamp()),
+            Self::UnixMillis => Value::from(timestamp.timestamp_millis()),
+            Self::UnixMicros => Value::from(timestamp.timestamp_micros()),
+            Self::Custom(format) => {
+                let mut formatted = String::new();
+                write!(formatted, "{}", timestamp.format(format))
+                    .map_err(|_| anyhow::anyhow!("invalid timestamp format: {format}"))?;
+                Value::from(formatted)
+            }
+        })
+    }
+}
+
+/// A backend that writes each entry as a single line of JSON, in the format
+/// known as JSON Lines. By default, each line is the serialized `Log`.
+///
+/// Output is buffered, and written out whenever the `Manager` flushes the
+/// backend.
+#[derive(Debug)]
+pub struct JsonLines {
+    writer: Box<dyn AsyncWriter>,
+    renames: Vec<(String, String)>,
+    timestamp_format: TimestampFormat,
+    flatten_payload: bool,
+}
+
+impl JsonLines {
+    /// Create a `JsonLines` backend that writes to `writer`
+    #[must_use]
+    pub fn new<W>(writer: W) -> Self
+    where
+        W: AsyncWrite + Send + Sync + std::fmt::Debug + Unpin + 'static,
+    {
+        Self {
+            writer: Box::new(writer),
+            renames: Vec::new(),
+            timestamp_format: TimestampFormat::default(),
+            flatten_payload: false,
+        }
+    }
+
+    /// Builder-style method to write the top-level field `field` as `name`.
+    /// Renames are applied after the payload is flattened, so fields from the
+    /// payload can be renamed as well.
+    #[must_use]
+    pub fn with_field_name<F: Into<String>, N: Into<String>>(mut self, field: F, name: N) -> Self {
+        let field = field.into();
+        self.renames.retain(|(renamed, _)| renamed != &field);
+        self.renames.push((field, name.into()));
+        self
+    }
+
+    /// Builder-style method to set the format timestamps are written in
+    #[must_use]
+    pub fn with_timestamp_format(mut self, timestamp_format: TimestampFormat) -> Self {
+        self.timestamp_format = timestamp_format;
+        self
+    }
+
+    /// Builder-style method to control whether the fields of each entry's
+    /// `payload` are written at the top level instead of within `payload`.
+    ///
+    /// Payload fields whose names conflict with the entry's own fields are
+    /// left in `payload`. Null payloads are omitted, and other payloads that
+    /// aren't objects are written within `payload`. Payloads are not
+    /// flattened by default.
+    #[must_use]
+    pub const fn with_flattened_payload(mut self, flatten_payload: bool) -> Self {
+        self.flatten_payload = flatten_payload;
+        self
+    }
+
+    /// Serializes `log` as a single line, appending it to `buffer`
+    fn write_line(&self, log: &Log, buffer: &mut Vec<u8>) -> anyhow::Result<()> {
+        let Value::Object(mut fields) = serde_json::to_value(log)? else {
+            anyhow::bail!("log entries always serialize as objects")
+        };
+
+        fields.insert(
+            String::from("timestamp"),
+            self.timestamp_format.format(log.timestamp)?,
+        );
+
+        if self.flatten_payload {
+            if let Some(Value::Object(payload)) = fields.remove("payload") {
+                let mut conflicting = Map::new();
+                for (key, value) in payload {
+                    if fields.contains_key(&key) {
+                        conflicting.insert(key, value);
+                    } else {
+                        fields.insert(key, value);
+                    }
+                }
+                if !conflicting.is_empty() {
+                    fields.insert(String::from("payload"), Value::Object(conflicting));
+                }
+            } else if !log.payload.is_null() {
+                fields.insert(String::from("payload"), log.payload.clone());
+            }
+        }
+
+        for (field, name) in &self.renames {
+            if let Some(value) = fields.remove(field) {
+                fields.insert(name.clone(), value);
+            }
+        }
+
+        serde_json::to_writer(&mut *buffer, &fields)?;
+        buffer.push(b'\n');
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Backend for JsonLines {
+    async fn process_log(&mut self, log: &Log) -> anyhow::Result<()> {
+        let mut line = Vec::new();
+        self.write_line(log, &mut line)?;
+        self.writer.write_all(&line).await?;
+        Ok(())
+    }
+
+    async fn process_batch(&mut self, logs: &[std::sync::Arc<Log>]) -> Result<(), BatchError> {
+        let mut lines = Vec::new();
+        for (processed, log) in logs.iter().enumerate() {
+            let written = lines.len();
+            if let Err(error) = self.write_line(log, &mut lines) {
+                // Write the entries before the one that failed, so that only
+                // the entries after it are passed in the next batch.
+                lines.truncate(written);
+                self.writer.write_all(&lines).await?;
+                return Err(BatchError { processed, error });
+            }
+        }
+        self.writer.write_all(&lines).await?;
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    async fn shutdown(&mut self) -> anyhow::Result<()> {
+        self.writer.close().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::TimeZone;
+    use futures::io::AllowStdIo;
+
+    use super::*;
+    use crate::{
+        backend::{test_entry, SharedBuffer},
+        Level, Location,
+    };
+
+    fn write(
+        mut backend: JsonLines,
+        output: &SharedBuffer,
+        log: &Log,
+    ) -> anyhow::Result<Vec<Value>> {
+        futures::executor::block_on(async {
+            backend
+                .process_batch(&[Arc::new(log.clone())])
+                .await
+                .map_err(|error| error.error)?;
+            backend.flush().await
+        })?;
+
+        let output = std::mem::take(&mut *output.0.lock().unwrap());
+        let mut lines = Vec::new();
+        for line in String::from_utf8(output)?.lines() {
+            lines.push(serde_json::from_str(line)?);
+        }
+        Ok(lines)
+    }
+
+    fn entry() -> Log {
+        Log {
+            timestamp: Utc.with_ymd_and_hms(2021, 1, 2, 3, 4, 5).unwrap(),
+            payload: serde_json::json!({ "user": 1, "message": "shadowed" }),
+            location: Some(Location::new("src/main.rs", 1, 2, "main")),
+            ..test_entry(Level::Info, "json_lines_test", "A")
+        }
+    }
+
+    #[test]
+    fn round_trip_test() -> anyhow::Result<()> {
+        let output = SharedBuffer::default();
+        let log = entry();
+        let lines = write(
+            JsonLines::new(AllowStdIo::new(output.clone())),
+            &output,
+            &log,
+        )?;
+
+        assert_eq!(lines.len(), 1);
+        assert_eq!(serde_json::from_value::<Log>(lines[0].clone())?, log);
+        Ok(())
+    }
+
+    #[test]
+    fn options_test() -> anyhow::Result<()> {
+        let output = SharedBuffer::default();
+        let backend = JsonLines::new(AllowStdIo::new(output.clone()))
+            .with_flattened_payload(true)
+            .with_timestamp_format(TimestampFormat::UnixMillis)
+            .with_field_name("message", "msg")
+            .with_field_name("user", "user_id");
+        let lines = write(backend, &output, &entry())?;
+
+        assert_eq!(
+            lines[0],
+            serde_json::json!({
+                "level": "Info",
+                "process": "json_lines_test",
+                "msg": "A",
+                "timestamp": 1_609_556_645_000_i64,
+                "user_id": 1,
+                "payload": { "message": "shadowed" },
+                "location": {
+                    "file": "src/main.rs",
+                    "line": 1,
+                    "column": 2,
+                    "module_path": "main",
+                },
+            })
+        );
+
+        let backend = JsonLines::new(AllowStdIo::new(output.clone()))
+            .with_timestamp_format(TimestampFormat::Custom(Cow::Borrowed("%Y-%m-%d %H:%M")));
+        let lines = write(backend, &output, &entry())?;
+        assert_eq!(lines[0]["timestamp"], "2021-01-02 03:04");
+        Ok(())
+    }
+}
@@ -1,7 +1,7 @@
 use std::{collections::VecDeque, sync::Arc};
 
 use async_trait::async_trait;
-use tokio::sync::Mutex;
+use futures::lock::Mutex;
 
 use crate::{backend::Backend, Log};
 
@@ -29,12 +29,9 @@ impl Memory {
 impl Backend for Memory {
     async fn process_log(&mut self, log: &Log) -> anyhow::Result<()> {
         let mut entries = self.entries.lock().await;
-
         entries.push_front(log.clone());
-
-        while entries.len() > self.max_entries {
-            entries.pop_back();
-        }
+        entries.truncate(self.max_entries);
+        drop(entries);
 
         Ok(())
     }
@@ -42,8 +39,6 @@ impl Backend for Memory {
 
 #[cfg(test)]
 mod tests {
-    use std::time::Duration;
-
     use crate::{Configuration, Manager};
 
     use super::*;
@@ -52,13 +47,13 @@ mod tests {
     async fn send_test() -> anyhow::Result<()> {
         let test_backend = Memory::new(2);
         let entries = test_backend.entries.clone();
-        let sender = Manager::default()
+        let handle = Manager::default()
             .with_backend(test_backend)
             .launch(|task| {
                 tokio::spawn(task);
             });
 
-        Configuration::named("send_test", sender)
+        Configuration::named("send_test", handle.clone())
             .run(async {
                 Log::info("A").submit();
                 Log::info("B").submit();
@@ -66,9 +61,9 @@ mod tests {
             })
             .await;
 
-        tokio::time::sleep(Duration::from_millis(1)).await;
+        handle.flush().await;
         {
-            let entries = entries.lock().await;
+            let entries = entries.lock().await.clone();
             assert_eq!(entries.len(), 2);
             assert_eq!(entries[0].message, "A");
             assert_eq!(entries[1].message, "B");
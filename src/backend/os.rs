@@ -1,65 +1,265 @@
-use std::fmt::Debug;
+use std::{
+    fmt::Debug,
+    io::{BufWriter, IsTerminal},
+};
 
 use async_trait::async_trait;
-use tokio::io::{stderr, stdout, AsyncWrite, AsyncWriteExt};
+use futures::io::{AllowStdIo, AsyncWrite, AsyncWriteExt};
 
-use crate::Level;
+use crate::{Level, Log};
 
-use super::Backend;
+use super::{AsyncWriter, Backend, ColorChoice, Compact, Formatter};
 
-trait AsyncWriter: AsyncWrite + Send + Sync + Debug + Unpin + 'static {}
-
-impl<T> AsyncWriter for T where T: AsyncWrite + Send + Sync + Debug + Unpin + 'static {}
-
-/// An IO-based backend, useful for outputting to files or pipes
+/// An IO-based backend, useful for outputting to files or pipes. Output is
+/// buffered, and written out whenever the `Manager` flushes the backend.
+///
+/// Entries are formatted using `Compact` unless another `Formatter` is
+/// provided, such as `Compact::default().with_location(true)` to write the
+/// location of each entry. By default, entries are colored when written to a
+/// terminal, as controlled by `ColorChoice::Auto`.
 #[derive(Debug)]
 pub struct Os {
-    err: Box<dyn AsyncWriter>,
-    default: Box<dyn AsyncWriter>,
+    err: Pipe,
+    default: Pipe,
+    formatter: Box<dyn Formatter>,
+    color: ColorChoice,
+}
+
+/// One of the destinations of an `Os` backend
+#[derive(Debug)]
+struct Pipe {
+    writer: Box<dyn AsyncWriter>,
+    is_terminal: bool,
+    colored: bool,
+}
+
+impl Pipe {
+    fn new<W: AsyncWrite + Send + Sync + Debug + Unpin + 'static>(writer: W) -> Self {
+        Self {
+            writer: Box::new(writer),
+            is_terminal: false,
+            colored: false,
+        }
+    }
 }
 
 impl Os {
-    /// Create an `Os` backend that sends `Level::Warning` or higher messages to `tokio::io::stderr()`, and all other messages to `tokio::io::stdout()`
+    /// Create an `Os` backend that sends `Level::Warning` or higher messages to
+    /// `err`, and all other messages to `default`. Any `futures::io::AsyncWrite`
+    /// implementor can be used, regardless of the async executor the `Manager`
+    /// is launched with.
+    ///
+    /// `err` and `default` are assumed not to be terminals, so they are only
+    /// colored if requested using `with_color()` or `CLICOLOR_FORCE`.
     #[must_use]
-    pub fn std() -> Self {
+    pub fn new<E, D>(err: E, default: D) -> Self
+    where
+        E: AsyncWrite + Send + Sync + Debug + Unpin + 'static,
+        D: AsyncWrite + Send + Sync + Debug + Unpin + 'static,
+    {
         Self {
-            err: Box::new(stderr()),
-            default: Box::new(stdout()),
+            err: Pipe::new(err),
+            default: Pipe::new(default),
+            formatter: Box::new(Compact::default()),
+            color: ColorChoice::default(),
         }
+        .with_color(ColorChoice::default())
+    }
+
+    /// Builder-style method to control whether entries are colored
+    #[must_use]
+    pub fn with_color(mut self, color: ColorChoice) -> Self {
+        self.color = color;
+        self.err.colored = color.enabled(self.err.is_terminal);
+        self.default.colored = color.enabled(self.default.is_terminal);
+        self
+    }
+
+    /// Records whether `err` and `default` are terminals, which determines
+    /// whether they are colored
+    fn with_terminals(mut self, err: bool, default: bool) -> Self {
+        self.err.is_terminal = err;
+        self.default.is_terminal = default;
+        let color = self.color;
+        self.with_color(color)
+    }
+
+    /// Builder-style method to set the `Formatter` entries are written with
+    #[must_use]
+    pub fn with_formatter<F: Formatter + 'static>(mut self, formatter: F) -> Self {
+        self.formatter = Box::new(formatter);
+        self
+    }
+
+    /// Create an `Os` backend that sends `Level::Warning` or higher messages to `tokio::io::stderr()`, and all other messages to `tokio::io::stdout()`
+    #[cfg(feature = "tokio")]
+    #[must_use]
+    pub fn std() -> Self {
+        use tokio::io::{stderr, stdout, BufWriter};
+
+        Self::new(
+            TokioWriter(BufWriter::new(stderr())),
+            TokioWriter(BufWriter::new(stdout())),
+        )
+        .with_terminals(
+            std::io::stderr().is_terminal(),
+            std::io::stdout().is_terminal(),
+        )
+    }
+
+    /// Create an `Os` backend that sends `Level::Warning` or higher messages to
+    /// `std::io::stderr()`, and all other messages to `std::io::stdout()`. This
+    /// works with any async executor, but writes block the backend's task
+    /// while the output is being flushed.
+    #[must_use]
+    pub fn std_blocking() -> Self {
+        Self::new(
+            AllowStdIo::new(BufWriter::new(std::io::stderr())),
+            AllowStdIo::new(BufWriter::new(std::io::stdout())),
+        )
+        .with_terminals(
+            std::io::stderr().is_terminal(),
+            std::io::stdout().is_terminal(),
+        )
+    }
+}
+
+/// Adapts a `tokio::io::AsyncWrite` implementor to `futures::io::AsyncWrite`
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+struct TokioWriter<W>(W);
+
+#[cfg(feature = "tokio")]
+impl<W: tokio::io::AsyncWrite + Unpin> AsyncWrite for TokioWriter<W> {
+    fn poll_write(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        std::pin::Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::pin::Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_close(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::pin::Pin::new(&mut self.0).poll_shutdown(cx)
     }
 }
 
 #[async_trait]
 impl Backend for Os {
-    async fn process_log(&mut self, log: &crate::Log) -> anyhow::Result<()> {
+    async fn process_log(&mut self, log: &Log) -> anyhow::Result<()> {
         let pipe = if log.level >= Level::Warning {
             &mut self.err
         } else {
             &mut self.default
         };
 
-        let message = format_args!(
-            "{} [{}] [{}]: {}\n",
-            fixed_width_level(log.level),
-            log.timestamp.to_rfc3339(),
-            log.process,
-            log.message.to_string(),
-        )
-        .to_string();
+        let mut line = String::new();
+        if pipe.colored {
+            self.formatter.format_colored(log, &mut line);
+        } else {
+            self.formatter.format(log, &mut line);
+        }
+        pipe.writer.write_all(line.as_bytes()).await?;
 
-        pipe.write_all(message.as_bytes()).await?;
-        pipe.flush().await?;
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> anyhow::Result<()> {
+        self.default.writer.flush().await?;
+        self.err.writer.flush().await?;
+
+        Ok(())
+    }
+
+    async fn shutdown(&mut self) -> anyhow::Result<()> {
+        self.default.writer.close().await?;
+        self.err.writer.close().await?;
 
         Ok(())
     }
 }
 
-const fn fixed_width_level(level: Level) -> &'static str {
-    match level {
-        Level::Trace => "TRACE",
-        Level::Debug => "DEBUG",
-        Level::Info => "INFO ",
-        Level::Warning => "WARN ",
-        Level::Error => "ERROR",
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        backend::{test_entry, SharedBuffer},
+        Location,
+    };
+
+    #[test]
+    fn location_test() -> anyhow::Result<()> {
+        let output = SharedBuffer::default();
+        let log = Log {
+            location: Some(Location::new("src/main.rs", 1, 2, "main")),
+            ..test_entry(Level::Info, "location_test", "A")
+        };
+
+        futures::executor::block_on(async {
+            let mut os = Os::new(
+                AllowStdIo::new(output.clone()),
+                AllowStdIo::new(output.clone()),
+            )
+            .with_color(ColorChoice::Never);
+            os.process_log(&log).await?;
+            os = os.with_formatter(Compact::default().with_location(true));
+            os.process_log(&log).await?;
+            os.flush().await
+        })?;
+
+        let output = String::from_utf8(output.0.lock().unwrap().clone())?;
+        let lines = output.lines().collect::<Vec<_>>();
+        assert!(lines[0].ends_with("[location_test]: A"));
+        assert!(lines[1].ends_with("[location_test]: A (src/main.rs:1:2)"));
+        Ok(())
+    }
+
+    #[test]
+    fn color_test() -> anyhow::Result<()> {
+        let err = SharedBuffer::default();
+        let default = SharedBuffer::default();
+        let log = |level| test_entry(level, "color_test", "A");
+
+        futures::executor::block_on(async {
+            let mut os = Os::new(
+                AllowStdIo::new(err.clone()),
+                AllowStdIo::new(default.clone()),
+            )
+            // Only stderr is a terminal.
+            .with_terminals(true, false);
+            os.process_log(&log(Level::Error)).await?;
+            os.process_log(&log(Level::Info)).await?;
+            os = os.with_color(ColorChoice::Never);
+            os.process_log(&log(Level::Info)).await?;
+            os = os.with_color(ColorChoice::Always);
+            os.process_log(&log(Level::Info)).await?;
+            os.flush().await
+        })?;
+
+        let err = String::from_utf8(err.0.lock().unwrap().clone())?;
+        let default = String::from_utf8(default.0.lock().unwrap().clone())?;
+        let default = default.lines().collect::<Vec<_>>();
+        // Automatic color depends on NO_COLOR and CLICOLOR_FORCE.
+        assert_eq!(
+            err.starts_with("\x1b[1;31mERROR\x1b[0m "),
+            ColorChoice::Auto.enabled(true)
+        );
+        assert_eq!(
+            default[0].starts_with("\x1b[32mINFO \x1b[0m "),
+            ColorChoice::Auto.enabled(false)
+        );
+        assert!(default[1].starts_with("INFO  ["));
+        assert!(default[2].starts_with("\x1b[32mINFO \x1b[0m \x1b[2m["));
+        Ok(())
     }
 }
@@ -1,37 +1,62 @@
-use std::{fmt::Display, sync::Arc};
+use std::{
+    fmt::Display,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+};
 
-use flume::Sender;
 use futures::Future;
 use once_cell::sync::OnceCell;
 
-use crate::Log;
+use crate::{
+    context::{Scoped, TaskContext},
+    Level, Log, LogError, ManagerHandle,
+};
 
 /// The global logging configuration
 static GLOBAL_CONFIG: OnceCell<Arc<Configuration>> = OnceCell::new();
 
-tokio::task_local! {
-    /// The async-task-local logging configuration
-    static TASK_CONFIG: Option<Arc<Configuration>>;
-}
+/// The global `OnFailure` behavior
+static ON_FAILURE: AtomicU8 = AtomicU8::new(OnFailure::Stderr as u8);
 
 /// A logging configuration
 #[derive(Debug)]
 pub struct Configuration {
     /// the destination for log messages to be delivered
-    pub destination: Sender<Arc<Log>>,
+    pub destination: ManagerHandle,
     /// the name of the process that generates the logs being sent
     pub process: String,
+    /// entries below this level are discarded when submitted
+    pub minimum_level: Level,
 }
 
 impl Configuration {
     /// Create a new configuration using the
-    pub fn named<S: Display>(process: S, sender: Sender<Arc<Log>>) -> Self {
+    pub fn named<S: Display>(process: S, destination: ManagerHandle) -> Self {
         Self {
-            destination: sender,
+            destination,
             process: process.to_string(),
+            minimum_level: Level::Trace,
         }
     }
 
+    /// Builder-style method to set `minimum_level`
+    #[must_use]
+    pub const fn with_minimum_level(mut self, minimum_level: Level) -> Self {
+        self.minimum_level = minimum_level;
+        self
+    }
+
+    /// Returns true if entries at `level` would be submitted by the current
+    /// configuration. The logging macros check this before formatting their
+    /// message. If no configuration is available, this returns true so that
+    /// the failure to log can be handled.
+    #[must_use]
+    pub fn enabled(level: Level) -> bool {
+        Self::current().is_none_or(|config| level >= config.minimum_level)
+    }
+
     /// Set the global logging configuration. If no other configuration is
     /// found, this one is used.
     ///
@@ -42,15 +67,180 @@ impl Configuration {
         GLOBAL_CONFIG.set(Arc::new(config)).unwrap();
     }
 
+    /// Sets how the logging macros handle entries that could not be logged.
+    /// By default, `OnFailure::Stderr` is used.
+    pub fn set_on_failure(on_failure: OnFailure) {
+        ON_FAILURE.store(on_failure as u8, Ordering::SeqCst);
+    }
+
+    /// Returns how the logging macros handle entries that could not be logged
+    #[must_use]
+    pub fn on_failure() -> OnFailure {
+        match ON_FAILURE.load(Ordering::SeqCst) {
+            0 => OnFailure::Stderr,
+            1 => OnFailure::Drop,
+            _ => OnFailure::Panic,
+        }
+    }
+
     /// Executes a `Future` with the configuration. Log messages from within
     /// code executed by the future will be submitted through this configuration
     pub async fn run<F: Future<Output = R> + Send, R: Send>(self, future: F) -> R {
-        TASK_CONFIG.scope(Some(Arc::new(self)), future).await
+        self.scope(future).await
+    }
+
+    /// Wraps `future` so that log messages from within code executed by the
+    /// future are submitted through this configuration. Unlike `run()`, the
+    /// returned future can be spawned onto any async executor. The fields and
+    /// span of the context the returned future is polled within are kept.
+    pub fn scope<F: Future>(self, future: F) -> Scoped<F> {
+        Scoped::layered(
+            TaskContext {
+                config: Some(Arc::new(self)),
+                ..TaskContext::default()
+            },
+            future,
+        )
+    }
+
+    /// Wraps `future` so that it uses the configuration and fields of the
+    /// caller. Futures spawned onto an async executor do not inherit the
+    /// context of the task that spawned them unless they are wrapped using
+    /// this function.
+    pub fn propagate<F: Future>(future: F) -> Scoped<F> {
+        Scoped::replacing(TaskContext::current(), future)
     }
 
     pub(crate) fn current() -> Option<Arc<Self>> {
-        TASK_CONFIG
-            .try_with(Clone::clone)
-            .unwrap_or_else(|_| GLOBAL_CONFIG.get().cloned())
+        TaskContext::current()
+            .config
+            .or_else(|| GLOBAL_CONFIG.get().cloned())
+    }
+}
+
+/// Controls how the logging macros handle entries that could not be logged.
+/// Entries dropped because the `Manager`'s queue is full are already reported
+/// by the `Manager`, and are not considered failures.
+#[derive(Default, Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub enum OnFailure {
+    /// Print the error, and the entry if one was created, to stderr
+    #[default]
+    Stderr,
+    /// Silently drop the entry
+    Drop,
+    /// Panic with the error
+    Panic,
+}
+
+impl OnFailure {
+    /// Handles `error`, which occurred while logging `log`
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is `OnFailure::Panic`
+    pub fn handle(self, error: &LogError, log: Option<&Log>) {
+        if matches!(error, LogError::QueueFull) {
+            return;
+        }
+
+        match (self, log) {
+            (Self::Stderr, Some(log)) => eprintln!(
+                "sirlog: unable to log entry ({error}): {:?} [{}] {}",
+                log.level, log.process, log.message
+            ),
+            (Self::Stderr, None) => eprintln!("sirlog: unable to log entry: {error}"),
+            (Self::Drop, _) => {}
+            (Self::Panic, _) => panic!("unable to log entry: {}", error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::{block_on, ThreadPool};
+
+    use super::*;
+    use crate::{backend::Memory, Log, Manager};
+
+    fn processes(entries: &std::collections::VecDeque<Log>) -> Vec<(String, String)> {
+        entries
+            .iter()
+            .rev()
+            .map(|log| (log.process.clone(), log.message.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn thread_pool_test() {
+        let pool = ThreadPool::new().unwrap();
+        let memory = Memory::new(10);
+        let entries = memory.entries.clone();
+        let spawner = pool.clone();
+        let handle = Manager::default()
+            .with_backend(memory)
+            .launch(move |task| spawner.spawn_ok(task));
+
+        block_on(async {
+            let (sender, receiver) = flume::bounded(1);
+            Configuration::named("outer", handle.clone())
+                .run(async {
+                    Log::info("A").submit();
+                    Configuration::named("inner", handle.clone())
+                        .run(async { Log::info("B").submit() })
+                        .await;
+                    pool.spawn_ok(Configuration::propagate(async move {
+                        Log::info("C").submit();
+                        sender.send(()).unwrap();
+                    }));
+                    Log::info("D").submit();
+                })
+                .await;
+            receiver.recv_async().await.unwrap();
+            handle.flush().await;
+
+            let mut entries = processes(&*entries.lock().await);
+            // "C" is submitted from another thread, so it may arrive in any
+            // position relative to "D"
+            entries.sort();
+            assert_eq!(
+                entries,
+                vec![
+                    (String::from("inner"), String::from("B")),
+                    (String::from("outer"), String::from("A")),
+                    (String::from("outer"), String::from("C")),
+                    (String::from("outer"), String::from("D")),
+                ]
+            );
+            handle.shutdown().await;
+        });
+    }
+
+    #[test]
+    fn smol_test() {
+        let memory = Memory::new(10);
+        let entries = memory.entries.clone();
+        let handle = Manager::default()
+            .with_backend(memory)
+            .launch(|task| smol::spawn(task).detach());
+
+        smol::block_on(async {
+            smol::spawn(Configuration::named("smol", handle.clone()).scope(async {
+                Log::info("A").submit();
+                smol::future::yield_now().await;
+                Log::info("B").submit();
+            }))
+            .await;
+            handle.flush().await;
+
+            assert_eq!(
+                processes(&*entries.lock().await),
+                vec![
+                    (String::from("smol"), String::from("A")),
+                    (String::from("smol"), String::from("B")),
+                ]
+            );
+            handle.shutdown().await;
+        });
     }
 }
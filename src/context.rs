@@ -0,0 +1,312 @@
+use std::{
+    cell::RefCell,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures::Future;
+use serde::Serialize;
+
+use crate::{Configuration, LogError};
+
+/// The fields added to every entry created within a context
+pub type Fields = serde_json::Map<String, serde_json::Value>;
+
+thread_local! {
+    /// The context of the `Scoped` future currently being polled on this
+    /// thread
+    static TASK_CONTEXT: RefCell<TaskContext> = const { RefCell::new(TaskContext::EMPTY) };
+}
+
+/// The logging context an async task executes with
+#[derive(Clone, Debug, Default)]
+pub struct TaskContext {
+    pub config: Option<Arc<Configuration>>,
+    pub fields: Option<Arc<Fields>>,
+    /// The id of the innermost span being run
+    pub span: Option<u64>,
+}
+
+impl TaskContext {
+    const EMPTY: Self = Self {
+        config: None,
+        fields: None,
+        span: None,
+    };
+
+    /// Returns the context of the task currently executing on this thread
+    pub fn current() -> Self {
+        TASK_CONTEXT.with(|context| context.borrow().clone())
+    }
+
+    /// Makes `context` the context of the task currently executing on this
+    /// thread, returning the previous context
+    pub fn replace(context: Self) -> Self {
+        TASK_CONTEXT.with(|current| current.replace(context))
+    }
+
+    /// Returns this context with `layer` applied on top of it. The layer's
+    /// configuration and span replace this context's, and the layer's fields
+    /// shadow this context's fields with the same keys.
+    pub fn with_layer(&self, layer: &Self) -> Self {
+        let fields = match (&self.fields, &layer.fields) {
+            (Some(outer), Some(inner)) => {
+                let mut merged = Fields::clone(outer);
+                merged.extend(
+                    inner
+                        .iter()
+                        .map(|(key, value)| (key.clone(), value.clone())),
+                );
+                Some(Arc::new(merged))
+            }
+            (outer, inner) => inner.clone().or_else(|| outer.clone()),
+        };
+        Self {
+            config: layer.config.clone().or_else(|| self.config.clone()),
+            fields,
+            span: layer.span.or(self.span),
+        }
+    }
+}
+
+/// Executes `future` with `fields` added to the payload of every entry
+/// created from within it.
+///
+/// Scopes can be nested: a field in an inner scope shadows a field with the
+/// same key in an outer scope, and fields added to an entry using `Log::add()`
+/// shadow the fields of every scope.
+///
+/// The returned future can be spawned onto any async executor. Fields that
+/// cannot be serialized are handled using the global `OnFailure` behavior.
+pub fn with_fields<I, K, V, F>(fields: I, future: F) -> Scoped<F>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: Into<String>,
+    V: Serialize,
+    F: Future,
+{
+    let mut added = Fields::new();
+    extend_fields(&mut added, fields);
+    Scoped::layered(
+        TaskContext {
+            fields: Some(Arc::new(added)),
+            ..TaskContext::default()
+        },
+        future,
+    )
+}
+
+/// Serializes each value in `fields` into `target`, replacing existing values.
+/// Values that cannot be serialized are handled using the global `OnFailure`
+/// behavior.
+pub fn extend_fields<I, K, V>(target: &mut Fields, fields: I)
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: Into<String>,
+    V: Serialize,
+{
+    for (key, value) in fields {
+        match serde_json::to_value(value) {
+            Ok(value) => {
+                target.insert(key.into(), value);
+            }
+            Err(error) => Configuration::on_failure().handle(&LogError::from(error), None),
+        }
+    }
+}
+
+/// A `Future` that executes with a logging context, returned from
+/// `Configuration::scope()`, `Configuration::propagate()` and `with_fields()`
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct Scoped<F> {
+    context: TaskContext,
+    layered: bool,
+    future: Pin<Box<F>>,
+}
+
+impl<F: Future> Scoped<F> {
+    /// Executes `future` with `layer` applied on top of the context that is
+    /// active each time the future is polled
+    pub(crate) fn layered(layer: TaskContext, future: F) -> Self {
+        Self {
+            context: layer,
+            layered: true,
+            future: Box::pin(future),
+        }
+    }
+
+    /// Executes `future` with `context`, regardless of the context that is
+    /// active when the future is polled
+    pub(crate) fn replacing(context: TaskContext, future: F) -> Self {
+        Self {
+            context,
+            layered: false,
+            future: Box::pin(future),
+        }
+    }
+}
+
+impl<F: Future> Future for Scoped<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let previous = TASK_CONTEXT.with(|context| {
+            let scoped = if self.layered {
+                context.borrow().with_layer(&self.context)
+            } else {
+                self.context.clone()
+            };
+            context.replace(scoped)
+        });
+        // Restore the outer context even if the future panics
+        let _restore = Restore(previous);
+        self.future.as_mut().poll(cx)
+    }
+}
+
+/// Reinstalls the context that was active before a `Scoped` future was polled
+struct Restore(TaskContext);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let previous = std::mem::take(&mut self.0);
+        TASK_CONTEXT.with(|context| *context.borrow_mut() = previous);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{backend::Memory, Log, Manager};
+
+    #[tokio::test]
+    async fn with_fields_test() -> anyhow::Result<()> {
+        let memory = Memory::new(10);
+        let entries = memory.entries.clone();
+        let handle = Manager::default().with_backend(memory).launch(|task| {
+            tokio::spawn(task);
+        });
+
+        with_fields(
+            [("request_id", 1)],
+            Configuration::named("with_fields_test", handle.clone()).run(async {
+                Log::info("A").submit();
+                with_fields([("request_id", 2), ("user_id", 3)], async {
+                    Log::info("B").submit();
+                    Log::info("C").with("request_id", 4)?.submit();
+                    tokio::spawn(Configuration::propagate(async {
+                        Log::info("D").submit();
+                    }))
+                    .await?;
+                    anyhow::Result::<()>::Ok(())
+                })
+                .await?;
+                Log::info("E").submit();
+                anyhow::Result::<()>::Ok(())
+            }),
+        )
+        .await?;
+        handle.flush().await;
+
+        let entries = entries.lock().await.clone();
+        let payloads = entries
+            .iter()
+            .rev()
+            .map(|log| (log.message.as_str(), log.payload.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            payloads,
+            vec![
+                ("A", serde_json::json!({"request_id": 1})),
+                ("B", serde_json::json!({"request_id": 2, "user_id": 3})),
+                ("C", serde_json::json!({"request_id": 4, "user_id": 3})),
+                ("D", serde_json::json!({"request_id": 2, "user_id": 3})),
+                ("E", serde_json::json!({"request_id": 1})),
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn layered_scopes_test() -> anyhow::Result<()> {
+        let memory = Memory::new(10);
+        let entries = memory.entries.clone();
+        let handle = Manager::default().with_backend(memory).launch(|task| {
+            tokio::spawn(task);
+        });
+
+        // The scopes are built before they are polled within each other, so
+        // each must apply its own layer to the context active when polled.
+        Configuration::named("layered_scopes_test", handle.clone())
+            .scope(with_fields([("a", 1)], async {
+                Log::info("A").submit();
+            }))
+            .await;
+        Configuration::named("layered_scopes_test", handle.clone())
+            .scope(with_fields(
+                [("a", 1), ("b", 1)],
+                with_fields([("b", 2)], async {
+                    Log::info("B").submit();
+                }),
+            ))
+            .await;
+        handle.flush().await;
+
+        let entries = entries.lock().await.clone();
+        let payloads = entries
+            .iter()
+            .rev()
+            .map(|log| (log.message.as_str(), log.payload.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            payloads,
+            vec![
+                ("A", serde_json::json!({"a": 1})),
+                ("B", serde_json::json!({"a": 1, "b": 2})),
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn created_in_scope_test() -> anyhow::Result<()> {
+        let memory = Memory::new(10);
+        let entries = memory.entries.clone();
+        let handle = Manager::default().with_backend(memory).launch(|task| {
+            tokio::spawn(task);
+        });
+
+        // Entries keep the fields of the scope they were created in, no matter
+        // where they are submitted
+        Configuration::named("created_in_scope_test", handle.clone())
+            .run(async {
+                let log = with_fields([("a", 1)], async { Log::info("A") }).await;
+                with_fields([("b", 2)], async {
+                    log.submit();
+                    Log::info("B").with("b", 3)?.submit();
+                    assert!(Log::info("C").with("b", 3)?.with("b", 4).is_err());
+                    anyhow::Result::<()>::Ok(())
+                })
+                .await
+            })
+            .await?;
+        handle.flush().await;
+
+        let entries = entries.lock().await.clone();
+        let payloads = entries
+            .iter()
+            .rev()
+            .map(|log| (log.message.as_str(), log.payload.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            payloads,
+            vec![
+                ("A", serde_json::json!({"a": 1})),
+                ("B", serde_json::json!({"b": 3})),
+            ]
+        );
+        Ok(())
+    }
+}
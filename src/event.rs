@@ -0,0 +1,296 @@
+use serde::{
+    ser::{Error, Impossible, SerializeStructVariant, SerializeTupleVariant},
+    Deserialize, Serialize, Serializer,
+};
+use serde_json::{Map, Value};
+
+/// A typed message, created from a `serde`-serializable enum variant
+///
+/// The name of the variant is kept as a stable `code`, which backends can use
+/// to filter and route entries without matching on their message. The
+/// variant's fields, if any, are kept in `data`.
+#[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct Event {
+    /// The name of the enum variant
+    pub code: String,
+    /// The fields of the enum variant, or `Null` for a unit variant
+    pub data: serde_json::Value,
+}
+
+impl Event {
+    /// Create a new event from the enum value `event`. The enum must use
+    /// serde's default, externally tagged representation.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `event` fails to serialize, or if it does not
+    /// serialize as an enum variant.
+    pub fn new<E: Serialize>(event: &E) -> Result<Self, serde_json::Error> {
+        event.serialize(EventSerializer)
+    }
+}
+
+/// Serializes an enum variant as an `Event`, rejecting every other value.
+/// Checking the variant itself, rather than the JSON it serializes to, keeps
+/// structs and internally tagged enums from being mistaken for variants.
+struct EventSerializer;
+
+fn not_a_variant() -> serde_json::Error {
+    serde_json::Error::custom("events must serialize as an externally tagged enum variant")
+}
+
+macro_rules! reject_values {
+    ($($method:ident($value:ty)),+ $(,)?) => {
+        $(
+            fn $method(self, _value: $value) -> Result<Event, serde_json::Error> {
+                Err(not_a_variant())
+            }
+        )+
+    };
+}
+
+impl Serializer for EventSerializer {
+    type Ok = Event;
+    type Error = serde_json::Error;
+    type SerializeSeq = Impossible<Event, serde_json::Error>;
+    type SerializeTuple = Impossible<Event, serde_json::Error>;
+    type SerializeTupleStruct = Impossible<Event, serde_json::Error>;
+    type SerializeTupleVariant = VariantFields<Vec<Value>>;
+    type SerializeMap = Impossible<Event, serde_json::Error>;
+    type SerializeStruct = Impossible<Event, serde_json::Error>;
+    type SerializeStructVariant = VariantFields<Map<String, Value>>;
+
+    reject_values!(
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_i128(i128),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_u128(u128),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+        serialize_bytes(&[u8]),
+        serialize_unit_struct(&'static str),
+    );
+
+    fn serialize_none(self) -> Result<Event, serde_json::Error> {
+        Err(not_a_variant())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, _value: &T) -> Result<Event, serde_json::Error> {
+        Err(not_a_variant())
+    }
+
+    fn serialize_unit(self) -> Result<Event, serde_json::Error> {
+        Err(not_a_variant())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Event, serde_json::Error> {
+        Ok(Event {
+            code: String::from(variant),
+            data: Value::Null,
+        })
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _value: &T,
+    ) -> Result<Event, serde_json::Error> {
+        Err(not_a_variant())
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Event, serde_json::Error> {
+        Ok(Event {
+            code: String::from(variant),
+            data: serde_json::to_value(value)?,
+        })
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, serde_json::Error> {
+        Err(not_a_variant())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, serde_json::Error> {
+        Err(not_a_variant())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, serde_json::Error> {
+        Err(not_a_variant())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, serde_json::Error> {
+        Ok(VariantFields {
+            code: variant,
+            fields: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, serde_json::Error> {
+        Err(not_a_variant())
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, serde_json::Error> {
+        Err(not_a_variant())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, serde_json::Error> {
+        Ok(VariantFields {
+            code: variant,
+            fields: Map::new(),
+        })
+    }
+}
+
+/// Collects the fields of a tuple or struct variant into the event's `data`
+struct VariantFields<F> {
+    code: &'static str,
+    fields: F,
+}
+
+impl SerializeTupleVariant for VariantFields<Vec<Value>> {
+    type Ok = Event;
+    type Error = serde_json::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), serde_json::Error> {
+        self.fields.push(serde_json::to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Event, serde_json::Error> {
+        Ok(Event {
+            code: String::from(self.code),
+            data: Value::Array(self.fields),
+        })
+    }
+}
+
+impl SerializeStructVariant for VariantFields<Map<String, Value>> {
+    type Ok = Event;
+    type Error = serde_json::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), serde_json::Error> {
+        self.fields
+            .insert(String::from(key), serde_json::to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Event, serde_json::Error> {
+        Ok(Event {
+            code: String::from(self.code),
+            data: Value::Object(self.fields),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    enum TestEvent {
+        Started,
+        UserJoined { id: u64 },
+        Renamed(String),
+        Moved(u64, u64),
+    }
+
+    #[derive(Serialize)]
+    #[serde(tag = "type")]
+    enum Tagged {
+        Started,
+    }
+
+    #[derive(Serialize)]
+    struct Wrapper {
+        id: u64,
+    }
+
+    #[derive(Serialize)]
+    struct NotAnEnum {
+        a: u64,
+        b: u64,
+    }
+
+    #[test]
+    fn event_tests() -> Result<(), serde_json::Error> {
+        assert_eq!(
+            Event::new(&TestEvent::Started)?,
+            Event {
+                code: String::from("Started"),
+                data: serde_json::Value::Null,
+            }
+        );
+        assert_eq!(
+            Event::new(&TestEvent::UserJoined { id: 42 })?,
+            Event {
+                code: String::from("UserJoined"),
+                data: serde_json::json!({"id": 42}),
+            }
+        );
+        assert_eq!(
+            Event::new(&TestEvent::Renamed(String::from("a")))?,
+            Event {
+                code: String::from("Renamed"),
+                data: serde_json::json!("a"),
+            }
+        );
+        assert_eq!(
+            Event::new(&TestEvent::Moved(1, 2))?,
+            Event {
+                code: String::from("Moved"),
+                data: serde_json::json!([1, 2]),
+            }
+        );
+        assert!(Event::new(&NotAnEnum { a: 1, b: 2 }).is_err());
+        assert!(Event::new(&Wrapper { id: 1 }).is_err());
+        assert!(Event::new(&Tagged::Started).is_err());
+        assert!(Event::new(&"free text").is_err());
+        Ok(())
+    }
+}
@@ -24,9 +24,30 @@
 /// logging backends (destinations)
 pub mod backend;
 mod configuration;
+mod context;
+mod event;
 mod log;
+#[cfg(feature = "log")]
+mod log_bridge;
 mod manager;
+mod panic;
+mod span;
+#[cfg(feature = "tracing")]
+mod tracing_layer;
 
-pub use self::{configuration::*, log::*, manager::*};
+pub use self::{
+    configuration::*,
+    context::{with_fields, Fields, Scoped},
+    event::*,
+    log::*,
+    manager::*,
+    panic::{install_panic_hook, PanicHook},
+    span::{span, Span, SpanGuard},
+};
+
+#[cfg(feature = "log")]
+pub use self::log_bridge::LogBridge;
+#[cfg(feature = "tracing")]
+pub use self::tracing_layer::TracingLayer;
 
 mod macros;
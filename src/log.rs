@@ -1,6 +1,10 @@
-use std::{fmt::Display, sync::Arc};
+use std::{
+    borrow::Cow,
+    fmt::{Debug, Display},
+    sync::Arc,
+};
 
-use crate::Configuration;
+use crate::{context::TaskContext, Configuration, Event};
 use chrono::{DateTime, Utc};
 use serde::{de::Error, Deserialize, Serialize};
 
@@ -32,6 +36,62 @@ pub struct Log {
     pub timestamp: DateTime<Utc>,
     /// Structured information that is relevant to the log message
     pub payload: serde_json::Value,
+    /// The location in the source code where the entry was created. This is
+    /// captured automatically by the logging macros.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<Location>,
+    /// The format string `message` was rendered from, if it was created using
+    /// the logging macros. Entries with the same template can be grouped
+    /// regardless of the values of their arguments. Named arguments are added
+    /// to `payload`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<Cow<'static, str>>,
+    /// The typed message this entry was created from, if it was created using
+    /// `Log::event()`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event: Option<Event>,
+    /// The keys of `payload` that were inherited from the context the entry
+    /// was created in, which `add()` replaces instead of rejecting
+    #[serde(skip)]
+    pub(crate) inherited: Vec<String>,
+}
+
+/// A location in the source code
+#[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct Location {
+    /// The source file, as returned by `file!()`
+    pub file: Cow<'static, str>,
+    /// The line within `file`, as returned by `line!()`
+    pub line: u32,
+    /// The column within `line`, as returned by `column!()`
+    pub column: u32,
+    /// The module containing the location, as returned by `module_path!()`
+    pub module_path: Cow<'static, str>,
+}
+
+impl Location {
+    /// Create a new location. The `location!()` macro creates a `Location` for
+    /// the code that invokes it.
+    #[must_use]
+    pub const fn new(
+        file: &'static str,
+        line: u32,
+        column: u32,
+        module_path: &'static str,
+    ) -> Self {
+        Self {
+            file: Cow::Borrowed(file),
+            line,
+            column,
+            module_path: Cow::Borrowed(module_path),
+        }
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
 }
 
 impl Log {
@@ -41,21 +101,94 @@ impl Log {
     ///
     /// This must be called when either a global `Configuration` is set or from
     /// within an async task that is executed within `Configuration::run()`
-    #[allow(clippy::clippy::needless_pass_by_value)] // This is a choice to make these APIs read cleaner, as Categories are always expected to be an enum constant.
     pub fn new<M: Display>(level: Level, message: M) -> Self {
+        match Self::try_new(level, message) {
+            Ok(log) => log,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    /// Create a new log entry with the `message` and `level` provided
+    ///
+    /// # Errors
+    ///
+    /// Returns `LogError::NoConfiguration` if neither a global `Configuration`
+    /// is set nor is this called from within an async task that is executed
+    /// within `Configuration::run()`
+    #[allow(clippy::needless_pass_by_value)] // This is a choice to make these APIs read cleaner, as Categories are always expected to be an enum constant.
+    pub fn try_new<M: Display>(level: Level, message: M) -> Result<Self, LogError> {
         let process = Configuration::current()
-            .expect("no task or global configuration found")
+            .ok_or(LogError::NoConfiguration)?
             .process
             .clone();
-        Self {
+        let mut log = Self {
             level,
             process,
             message: message.to_string(),
             timestamp: Utc::now(),
             payload: serde_json::Value::Null,
+            location: None,
+            template: None,
+            event: None,
+            inherited: Vec::new(),
+        };
+        log.inherit_context()?;
+        Ok(log)
+    }
+
+    /// Merges the fields of the current context into the payload, without
+    /// replacing any keys already present
+    fn inherit_context(&mut self) -> Result<(), LogError> {
+        let Some(fields) = TaskContext::current().fields else {
+            return Ok(());
+        };
+        if self.payload.is_null() {
+            self.payload = serde_json::Value::Object(serde_json::Map::new());
+        }
+        let Some(payload) = self.payload.as_object_mut() else {
+            return Err(LogError::Payload(serde_json::Error::custom(
+                "context fields can only be merged into an object payload",
+            )));
+        };
+        for (key, value) in fields.iter() {
+            if !payload.contains_key(key) {
+                payload.insert(key.clone(), value.clone());
+                self.inherited.push(key.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Create a new log entry with the `level` provided from the enum value
+    /// `event`. The name of the variant is used as the entry's message, and
+    /// is kept along with the variant's fields in `Log::event`.
+    ///
+    /// # Panics
+    ///
+    /// * If no `Configuration` is available
+    /// * If `event` does not serialize as an enum variant
+    pub fn event<E: Serialize>(level: Level, event: &E) -> Self {
+        match Self::try_event(level, event) {
+            Ok(log) => log,
+            Err(error) => panic!("{}", error),
         }
     }
 
+    /// Create a new log entry with the `level` provided from the enum value
+    /// `event`. The name of the variant is used as the entry's message, and
+    /// is kept along with the variant's fields in `Log::event`.
+    ///
+    /// # Errors
+    ///
+    /// * `LogError::NoConfiguration` if no `Configuration` is available
+    /// * `LogError::Payload` if `event` does not serialize as an enum variant
+    pub fn try_event<E: Serialize>(level: Level, event: &E) -> Result<Self, LogError> {
+        let event = Event::new(event)?;
+        let mut log = Self::try_new(level, &event.code)?;
+        log.event = Some(event);
+        Ok(log)
+    }
+
     /// Create a new log entry with `Level::Error` and the `message` provided
     ///
     /// # Panics
@@ -108,7 +241,8 @@ impl Log {
 
     /// Add extra information to this log entry, useful for attaching
     /// information that will help understand the entry or the context in which
-    /// it was created
+    /// it was created. Fields inherited from the context the entry was created
+    /// in are replaced, but adding the same key twice is an error.
     pub fn add<K: Into<String>, V: Serialize>(
         &mut self,
         key: K,
@@ -117,13 +251,21 @@ impl Log {
         if matches!(self.payload, serde_json::Value::Null) {
             self.payload = serde_json::Value::Object(serde_json::Map::new());
         }
+        let Some(payload) = self.payload.as_object_mut() else {
+            return Err(serde_json::Error::custom("the payload is not an object"));
+        };
         let key = key.into();
-        if self
-            .payload
-            .as_object_mut()
-            .unwrap()
+        let inherited = self
+            .inherited
+            .iter()
+            .position(|inherited| inherited == &key);
+        if let Some(index) = inherited {
+            self.inherited.swap_remove(index);
+        }
+        if payload
             .insert(key, serde_json::value::to_value(value)?)
             .is_some()
+            && inherited.is_none()
         {
             return Err(serde_json::Error::custom(
                 "attempting to add the same key twice",
@@ -144,26 +286,172 @@ impl Log {
         Ok(self)
     }
 
-    /// Submits this log entry to the current manager
+    /// Builder-style method to set `location`
+    #[must_use]
+    pub fn with_location(mut self, location: Location) -> Self {
+        self.location = Some(location);
+        self
+    }
+
+    /// Builder-style method to set `template`
+    #[must_use]
+    pub fn with_template<T: Into<Cow<'static, str>>>(mut self, template: T) -> Self {
+        self.template = Some(template.into());
+        self
+    }
+
+    /// Adds `value` to the payload, handling any failure using the global
+    /// `OnFailure` behavior. Used by the logging macros.
+    #[doc(hidden)]
+    #[must_use]
+    pub fn add_or_handle<K: Into<String>, V: Serialize>(mut self, key: K, value: V) -> Self {
+        if let Err(error) = self.add(key, value) {
+            Configuration::on_failure().handle(&LogError::from(error), Some(&self));
+        }
+        self
+    }
+
+    /// Submits this log entry to the current manager. Entries below the
+    /// configuration's `minimum_level` are discarded. If the manager's queue
+    /// is full, the entry is handled according to the manager's `Overflow`
+    /// strategy.
     ///
     /// # Panics
     ///
     /// * If no `Configuration` is available
     /// * If the manager is not able to receive the log message
     pub fn submit(self) {
-        let log = Arc::new(self);
-        Configuration::current()
-            .expect("no task or global configuration found")
-            .destination
-            .send(log)
-            .expect("error sending log to manager")
+        match self.try_submit() {
+            Ok(()) | Err(LogError::QueueFull) => {}
+            Err(error) => panic!("error sending log to manager: {}", error),
+        }
+    }
+
+    /// Submits this log entry to the current manager. Entries below the
+    /// configuration's `minimum_level` are discarded.
+    ///
+    /// # Errors
+    ///
+    /// * `LogError::NoConfiguration` if no `Configuration` is available
+    /// * `LogError::ManagerClosed` if the manager is no longer running
+    /// * `LogError::QueueFull` if the manager's queue is full and its
+    ///   `Overflow` strategy dropped the entry
+    pub fn try_submit(self) -> Result<(), LogError> {
+        match self.prepare_submission()? {
+            Some(config) => config.destination.send(Arc::new(self)),
+            None => Ok(()),
+        }
+    }
+
+    /// Submits this log entry to the current manager like `submit()`. If the
+    /// manager's queue is full and its `Overflow` strategy waits for room,
+    /// this waits without blocking the current thread, so it should be
+    /// preferred in async code.
+    ///
+    /// # Panics
+    ///
+    /// * If no `Configuration` is available
+    /// * If the manager is not able to receive the log message
+    pub async fn submit_async(self) {
+        match self.try_submit_async().await {
+            Ok(()) | Err(LogError::QueueFull) => {}
+            Err(error) => panic!("error sending log to manager: {}", error),
+        }
+    }
+
+    /// Submits this log entry to the current manager like `try_submit()`,
+    /// waiting for room in a full queue without blocking the current thread
+    ///
+    /// # Errors
+    ///
+    /// * `LogError::NoConfiguration` if no `Configuration` is available
+    /// * `LogError::ManagerClosed` if the manager is no longer running
+    /// * `LogError::QueueFull` if the manager's queue is full and its
+    ///   `Overflow` strategy dropped the entry
+    pub async fn try_submit_async(self) -> Result<(), LogError> {
+        match self.prepare_submission()? {
+            Some(config) => config.destination.send_async(Arc::new(self)).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the configuration to submit this entry through, or `None` if
+    /// the entry should be discarded
+    fn prepare_submission(&self) -> Result<Option<Arc<Configuration>>, LogError> {
+        let config = Configuration::current().ok_or(LogError::NoConfiguration)?;
+        if self.level < config.minimum_level {
+            return Ok(None);
+        }
+
+        Ok(Some(config))
+    }
+
+    /// Creates and submits an entry, handling any failure using the global
+    /// `OnFailure` behavior. Used by the logging macros.
+    #[doc(hidden)]
+    pub fn submit_or_handle(log: Result<Self, LogError>) {
+        let on_failure = Configuration::on_failure();
+        match log {
+            Ok(log) => match log.prepare_submission() {
+                Ok(Some(config)) => {
+                    let log = Arc::new(log);
+                    if let Err(error) = config.destination.send(log.clone()) {
+                        on_failure.handle(&error, Some(&log));
+                    }
+                }
+                Ok(None) => {}
+                Err(error) => on_failure.handle(&error, Some(&log)),
+            },
+            Err(error) => on_failure.handle(&error, None),
+        }
+    }
+}
+
+/// An error that prevented an entry from being logged
+#[derive(Debug)]
+pub enum LogError {
+    /// No global `Configuration` is set, and the entry was not created from
+    /// within an async task that is executed within `Configuration::run()`
+    NoConfiguration,
+    /// The `Manager` is no longer running
+    ManagerClosed,
+    /// The `Manager`'s queue is full, and its `Overflow` strategy dropped the
+    /// entry
+    QueueFull,
+    /// A value could not be added to the entry's payload
+    Payload(serde_json::Error),
+}
+
+impl Display for LogError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoConfiguration => f.write_str("no task or global configuration found"),
+            Self::ManagerClosed => f.write_str("the manager is no longer running"),
+            Self::QueueFull => f.write_str("the manager's queue is full"),
+            Self::Payload(error) => write!(f, "invalid payload: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for LogError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Payload(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for LogError {
+    fn from(error: serde_json::Error) -> Self {
+        Self::Payload(error)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{Configuration, Manager};
+    use crate::{backend::test_entry, Configuration, Manager};
 
     fn entries_eq_without_timestamps(a: &Log, b: &Log) -> bool {
         a.level == b.level
@@ -183,56 +471,32 @@ mod tests {
         .run(async {
             assert!(entries_eq_without_timestamps(
                 &Log::debug("A"),
-                &Log {
-                    level: Level::Debug,
-                    process: String::from("entry_building_tests"),
-                    message: String::from("A"),
-                    payload: serde_json::Value::Null,
-                    timestamp: Utc::now(),
-                }
+                &test_entry(Level::Debug, "entry_building_tests", "A")
             ));
 
             assert!(entries_eq_without_timestamps(
                 &Log::info("A"),
-                &Log {
-                    level: Level::Info,
-                    process: String::from("entry_building_tests"),
-                    message: String::from("A"),
-                    payload: serde_json::Value::Null,
-                    timestamp: Utc::now(),
-                }
+                &test_entry(Level::Info, "entry_building_tests", "A")
             ));
 
             assert!(entries_eq_without_timestamps(
                 &Log::warning("B"),
-                &Log {
-                    level: Level::Warning,
-                    process: String::from("entry_building_tests"),
-                    message: String::from("B"),
-                    payload: serde_json::Value::Null,
-                    timestamp: Utc::now(),
-                }
+                &test_entry(Level::Warning, "entry_building_tests", "B")
             ));
 
             assert!(entries_eq_without_timestamps(
                 Log::error("B").add("key", "value")?,
                 &Log {
-                    level: Level::Error,
-                    process: String::from("entry_building_tests"),
-                    message: String::from("B"),
                     payload: serde_json::json!({"key": "value"}),
-                    timestamp: Utc::now(),
+                    ..test_entry(Level::Error, "entry_building_tests", "B")
                 }
             ));
 
             assert!(entries_eq_without_timestamps(
                 Log::trace("B").add("key", "value")?.add("key2", "value2")?,
                 &Log {
-                    level: Level::Trace,
-                    process: String::from("entry_building_tests"),
-                    message: String::from("B"),
                     payload: serde_json::json!({"key": "value", "key2": "value2"}),
-                    timestamp: Utc::now(),
+                    ..test_entry(Level::Trace, "entry_building_tests", "B")
                 }
             ));
 
@@ -245,6 +509,74 @@ mod tests {
         })
         .await
     }
+
+    #[test]
+    fn location_serialization_test() -> Result<(), serde_json::Error> {
+        let mut log = test_entry(Level::Info, "location_serialization_test", "A");
+        assert!(serde_json::to_value(&log)?.get("location").is_none());
+
+        log = log.with_location(crate::location!());
+        let json = serde_json::to_value(&log)?;
+        assert_eq!(json["location"]["file"], file!());
+        assert_eq!(json["location"]["module_path"], module_path!());
+        assert_eq!(serde_json::from_value::<Log>(json)?, log);
+        Ok(())
+    }
+
+    #[test]
+    fn no_configuration_test() {
+        assert!(matches!(
+            Log::try_new(Level::Info, "A"),
+            Err(LogError::NoConfiguration)
+        ));
+        // The macros report the failure to stderr rather than panicking
+        crate::log!(Level::Info, "A", "key" => "value");
+    }
+
+    #[tokio::test]
+    async fn manager_closed_test() {
+        let handle = Manager::default().launch(|t| {
+            tokio::spawn(t);
+        });
+        handle.shutdown().await;
+
+        Configuration::named("manager_closed_test", handle)
+            .run(async {
+                assert!(matches!(
+                    Log::info("A").try_submit(),
+                    Err(LogError::ManagerClosed)
+                ));
+            })
+            .await;
+    }
+
+    #[test]
+    fn queue_full_test() {
+        // Keep the manager alive without ever running it, so that its queue
+        // never drains
+        let tasks = std::sync::Mutex::new(Vec::new());
+        let handle = Manager::default()
+            .with_capacity(1, crate::Overflow::DropNewest)
+            .launch(move |task| tasks.lock().unwrap().push(task));
+
+        futures::executor::block_on(Configuration::named("queue_full_test", handle).run(async {
+            assert!(Log::info("A").try_submit().is_ok());
+            assert!(matches!(
+                Log::info("B").try_submit(),
+                Err(LogError::QueueFull)
+            ));
+            // Dropped entries are not a failure when using `submit`
+            Log::info("C").submit();
+        }));
+    }
+
+    #[test]
+    #[should_panic(expected = "the manager is no longer running")]
+    fn on_failure_panic_test() {
+        crate::OnFailure::Drop.handle(&LogError::ManagerClosed, None);
+        crate::OnFailure::Panic.handle(&LogError::QueueFull, None);
+        crate::OnFailure::Panic.handle(&LogError::ManagerClosed, None);
+    }
 }
 
 // #[cfg(feature = "archiver")]
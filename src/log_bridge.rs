@@ -0,0 +1,156 @@
+use std::borrow::Cow;
+
+use crate::{Configuration, Level, Location, Log, LogError};
+
+/// A `log::Log` implementation that submits records from the `log` crate
+/// through the current `Configuration`
+///
+/// Each record's target is added to the payload as `target`, and its
+/// key-values are added to the payload under their keys. The record's file,
+/// line and module path are kept in `Log::location`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LogBridge;
+
+impl LogBridge {
+    /// Installs the bridge as the `log` crate's global logger, and sets the
+    /// `log` crate's maximum level to the current `Configuration`'s
+    /// `minimum_level`. If no configuration is available, every level is
+    /// enabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a global logger has already been installed
+    pub fn install() -> Result<(), ::log::SetLoggerError> {
+        ::log::set_logger(&Self)?;
+        let minimum_level =
+            Configuration::current().map_or(Level::Trace, |config| config.minimum_level);
+        ::log::set_max_level(level_filter(minimum_level));
+        Ok(())
+    }
+}
+
+impl ::log::Log for LogBridge {
+    fn enabled(&self, metadata: &::log::Metadata<'_>) -> bool {
+        Configuration::enabled(level(metadata.level()))
+    }
+
+    fn log(&self, record: &::log::Record<'_>) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        Log::submit_or_handle(
+            Log::try_new(level(record.level()), record.args()).map(|log| {
+                let mut log = log.add_or_handle("target", record.target());
+                if let (Some(file), Some(line)) = (record.file(), record.line()) {
+                    log.location = Some(Location {
+                        file: record
+                            .file_static()
+                            .map_or_else(|| Cow::Owned(file.to_string()), Cow::Borrowed),
+                        line,
+                        column: 0,
+                        module_path: record.module_path_static().map_or_else(
+                            || Cow::Owned(record.module_path().unwrap_or_default().to_string()),
+                            Cow::Borrowed,
+                        ),
+                    });
+                }
+
+                let _ = record.key_values().visit(&mut KeyValues(&mut log));
+                log
+            }),
+        );
+    }
+
+    fn flush(&self) {}
+}
+
+/// Adds each key-value pair of a record to an entry's payload
+struct KeyValues<'a>(&'a mut Log);
+
+impl<'kvs> ::log::kv::VisitSource<'kvs> for KeyValues<'_> {
+    fn visit_pair(
+        &mut self,
+        key: ::log::kv::Key<'kvs>,
+        value: ::log::kv::Value<'kvs>,
+    ) -> Result<(), ::log::kv::Error> {
+        let value = if let Some(value) = value.to_bool() {
+            serde_json::Value::from(value)
+        } else if let Some(value) = value.to_u64() {
+            serde_json::Value::from(value)
+        } else if let Some(value) = value.to_i64() {
+            serde_json::Value::from(value)
+        } else if let Some(value) = value.to_f64() {
+            serde_json::Value::from(value)
+        } else if let Some(value) = value.to_borrowed_str() {
+            serde_json::Value::from(value)
+        } else {
+            serde_json::Value::from(value.to_string())
+        };
+        if let Err(error) = self.0.add(key.as_str(), value) {
+            Configuration::on_failure().handle(&LogError::from(error), Some(self.0));
+        }
+        Ok(())
+    }
+}
+
+const fn level(level: ::log::Level) -> Level {
+    match level {
+        ::log::Level::Error => Level::Error,
+        ::log::Level::Warn => Level::Warning,
+        ::log::Level::Info => Level::Info,
+        ::log::Level::Debug => Level::Debug,
+        ::log::Level::Trace => Level::Trace,
+    }
+}
+
+const fn level_filter(level: Level) -> ::log::LevelFilter {
+    match level {
+        Level::Error => ::log::LevelFilter::Error,
+        Level::Warning => ::log::LevelFilter::Warn,
+        Level::Info => ::log::LevelFilter::Info,
+        Level::Debug => ::log::LevelFilter::Debug,
+        Level::Trace => ::log::LevelFilter::Trace,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{backend::Memory, Manager};
+
+    #[tokio::test]
+    async fn bridge_test() {
+        let memory = Memory::new(10);
+        let entries = memory.entries.clone();
+        let handle = Manager::default().with_backend(memory).launch(|task| {
+            tokio::spawn(task);
+        });
+
+        Configuration::named("bridge_test", handle.clone())
+            .with_minimum_level(Level::Info)
+            .run(async {
+                LogBridge::install().unwrap();
+                assert_eq!(::log::max_level(), ::log::LevelFilter::Info);
+
+                ::log::warn!(target: "db", rows = 5, slow = true; "query took {}ms", 12);
+                ::log::debug!("not delivered");
+            })
+            .await;
+        handle.flush().await;
+
+        let entries = entries.lock().await.clone();
+        assert_eq!(entries.len(), 1);
+        let log = &entries[0];
+        assert_eq!(log.level, Level::Warning);
+        assert_eq!(log.process, "bridge_test");
+        assert_eq!(log.message, "query took 12ms");
+        assert_eq!(
+            log.payload,
+            serde_json::json!({"target": "db", "rows": 5, "slow": true})
+        );
+        let location = log.location.as_ref().unwrap();
+        assert_eq!(location.file, file!());
+        assert_eq!(location.module_path, module_path!());
+    }
+}
@@ -1,16 +1,83 @@
+/// creates a `Location` for the code invoking this macro
+#[macro_export]
+macro_rules! location {
+    () => {
+        $crate::Location::new(file!(), line!(), column!(), module_path!())
+    };
+}
+
 /// logs a single message at the level provided
+///
+/// The message can be any `Display` value, or a format string followed by its
+/// arguments. A string literal message is always treated as a format string,
+/// and is kept in `Log::template`. Named format arguments passed as
+/// `name = value` are also added to the payload. Identifiers captured
+/// directly by the format string, such as `id` in `"user {id} left"`, are
+/// formatted but not added to the payload; pass them as `id = id` to record
+/// them. Payload values are provided as `key => value` pairs, separated from
+/// any format arguments by a semicolon:
+///
+/// ```rust,ignore
+/// log!(Level::Info, "user joined");
+/// log!(Level::Info, "user joined", "user" => id);
+/// log!(Level::Info, "user {} joined", id);
+/// log!(Level::Info, "user {} joined", id; "room" => room);
+/// log!(Level::Info, "user {user} bought {item}", user = id, item = sku);
+/// log!(Level::Info, "user {id} left", id = id);
+/// ```
+///
+/// Nothing is formatted or serialized if the current `Configuration` does not
+/// accept entries at the level provided.
 #[macro_export]
 macro_rules! log {
-    ($level:expr, $message:expr) => {
-        $crate::Log::new($level, $message).submit()
+    // Templates are passed through `concat!()` so that lints for format strings
+    // used outside of formatting macros are not triggered by the template.
+    (@submit $level:expr, $message:expr, [$($method:tt)*]; $($key:expr => $value:expr),*) => {
+        $crate::Log::submit_or_handle(match $crate::Log::try_new($level, $message) {
+            Ok(log) => Ok(log
+                .with_location($crate::location!())
+                $($method)*
+                $(.add_or_handle($key, $value))*),
+            Err(error) => Err(error),
+        })
     };
-    ($level:expr, $message:expr, $($key:expr => $value:expr),+) => {{
-        let mut log = $crate::Log::new($level, $message);
-        $(
-            log.add($key, $value).unwrap();
-        )+
-        log.submit()
+    (@entry $level:expr, $message:expr, [$($method:tt)*]; $($key:expr => $value:expr),*) => {{
+        let level = $level;
+        if $crate::Configuration::enabled(level) {
+            $crate::log!(@submit level, $message, [$($method)*]; $($key => $value),*)
+        }
+    }};
+    ($level:expr, $format:literal $(,)?) => {
+        $crate::log!(@entry $level, format_args!($format), [.with_template(concat!($format))];)
+    };
+    ($level:expr, $format:literal, $($key:expr => $value:expr),+ $(,)?) => {
+        $crate::log!(@entry $level, format_args!($format), [.with_template(concat!($format))]; $($key => $value),+)
+    };
+    ($level:expr, $message:expr $(,)?) => {
+        $crate::log!(@entry $level, $message, [];)
+    };
+    ($level:expr, $message:expr, $($key:expr => $value:expr),+ $(,)?) => {
+        $crate::log!(@entry $level, $message, []; $($key => $value),+)
+    };
+    ($level:expr, $format:literal, $($name:ident = $arg:expr),+ $(; $($key:expr => $value:expr),+)? $(,)?) => {{
+        let level = $level;
+        if $crate::Configuration::enabled(level) {
+            match ($(&$arg,)+) {
+                ($($name,)+) => $crate::log!(
+                    @submit level,
+                    format_args!($format, $($name = $name),+),
+                    [.with_template(concat!($format))];
+                    $(stringify!($name) => $name),+ $($(, $key => $value)+)?
+                ),
+            }
+        }
     }};
+    ($level:expr, $format:literal, $($arg:expr),+; $($key:expr => $value:expr),+ $(,)?) => {
+        $crate::log!(@entry $level, format_args!($format, $($arg),+), [.with_template(concat!($format))]; $($key => $value),+)
+    };
+    ($level:expr, $format:literal, $($arg:tt)+) => {
+        $crate::log!(@entry $level, format_args!($format, $($arg)+), [.with_template(concat!($format))];)
+    };
 }
 
 #[rustfmt::skip] // This macro confuses rustfmt and it tries to indent it with the inner lines very far to the right.
@@ -21,12 +88,9 @@ macro_rules! make_level_log_macro {
         #[doc = $docs]
         #[macro_export]
         macro_rules! $name {
-            ($message:expr) => {
-                $crate::log!($crate::Level::$level, $message)
+            ($d($d params:tt)+) => {
+                $crate::log!($crate::Level::$level, $d($d params)+)
             };
-            ($message:expr, $d($d params:tt)*) => {{
-                $crate::log!($crate::Level::$level, $message, $d($d params)*)
-            }};
         }
     };
 }
@@ -37,6 +101,7 @@ make_level_log_macro!($ info, Info, "logs a message with `Level::Info`");
 make_level_log_macro!($ debug, Debug, "logs a message with `Level::Debug`");
 make_level_log_macro!($ trace, Trace, "logs a message with `Level::Trace`");
 
+#[cfg(test)]
 #[tokio::test]
 async fn macro_tests() {
     use crate::{backend::Memory, Level, Manager};
@@ -54,16 +119,20 @@ async fn macro_tests() {
         log!(Level::Info, "A");
         tokio::time::sleep(Duration::from_millis(1)).await;
         {
-            let entries = entries.lock().await;
+            let entries = entries.lock().await.clone();
             assert_eq!(entries[0].level, Level::Info);
             assert_eq!(entries[0].message, "A");
         }
         log!(Level::Info, "B", "a" => 1_u64);
         tokio::time::sleep(Duration::from_millis(1)).await;
         {
-            let entries = entries.lock().await;
+            let entries = entries.lock().await.clone();
             assert_eq!(entries[0].level, Level::Info);
             assert_eq!(entries[0].payload, serde_json::json!({"a": 1_u64}));
+            let location = entries[0].location.as_ref().unwrap();
+            assert_eq!(location.file, file!());
+            assert_eq!(location.line, line!() - 8);
+            assert_eq!(location.module_path, module_path!());
         }
 
         macro_rules! test_log_level {
@@ -71,14 +140,14 @@ async fn macro_tests() {
                 $macroname!("A");
                 tokio::time::sleep(Duration::from_millis(1)).await;
                 {
-                    let entries = entries.lock().await;
+                    let entries = entries.lock().await.clone();
                     assert_eq!(entries[0].level, $level);
                     assert_eq!(entries[0].message, "A");
                 }
                 $macroname!("B", "a" => 1_u64);
                 tokio::time::sleep(Duration::from_millis(1)).await;
                 {
-                    let entries = entries.lock().await;
+                    let entries = entries.lock().await.clone();
                     assert_eq!(entries[0].level, $level);
                     assert_eq!(entries[0].payload, serde_json::json!({"a": 1_u64}));
                 }
@@ -96,3 +165,119 @@ async fn macro_tests() {
         .run(tests)
         .await;
 }
+
+#[cfg(test)]
+#[tokio::test]
+async fn format_args_tests() {
+    use std::{
+        fmt::Display,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use crate::{backend::Memory, Configuration, Level, Manager};
+
+    /// Counts the number of times it is formatted
+    struct Counted<'a>(&'a AtomicUsize);
+
+    impl Display for Counted<'_> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            f.write_str("counted")
+        }
+    }
+
+    let test_backend = Memory::new(10);
+    let entries = test_backend.entries.clone();
+    let handle = Manager::default()
+        .with_backend(test_backend)
+        .launch(|task| {
+            tokio::spawn(task);
+        });
+
+    let formatted = AtomicUsize::default();
+    Configuration::named("format_args_tests", handle.clone())
+        .with_minimum_level(Level::Info)
+        .run(async {
+            let id = 42;
+            info!("user {} joined", id);
+            warn!("user {id} left");
+            error!("user {} joined {}", id, "room"; "room" => "lobby", "id" => id);
+            debug!("not {}", Counted(&formatted));
+            info!("{}", Counted(&formatted));
+        })
+        .await;
+    handle.flush().await;
+
+    assert_eq!(formatted.load(Ordering::SeqCst), 1);
+    let entries = entries.lock().await.clone();
+    let messages = entries
+        .iter()
+        .rev()
+        .map(|log| log.message.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        vec![
+            "user 42 joined",
+            "user 42 left",
+            "user 42 joined room",
+            "counted"
+        ]
+    );
+    assert_eq!(
+        entries[1].payload,
+        serde_json::json!({"room": "lobby", "id": 42})
+    );
+    // Identifiers captured by the format string aren't recorded
+    assert_eq!(entries[2].template.as_deref(), Some("user {id} left"));
+    assert_eq!(entries[2].payload, serde_json::Value::Null);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn template_tests() {
+    use crate::{backend::Memory, Configuration, Level, Manager};
+
+    let test_backend = Memory::new(10);
+    let entries = test_backend.entries.clone();
+    let handle = Manager::default()
+        .with_backend(test_backend)
+        .launch(|task| {
+            tokio::spawn(task);
+        });
+
+    Configuration::named("template_tests", handle.clone())
+        .run(async {
+            let sku = String::from("ABC-1");
+            info!("user {user} bought {item}", user = 42, item = sku);
+            info!("user {user} bought {item}", user = 7, item = "XYZ-2"; "store" => "main");
+            info!("user {} joined", 42);
+            log!(Level::Info, String::from("not a template"));
+            let id = 42;
+            info!("user {id} left", id = id);
+        })
+        .await;
+    handle.flush().await;
+
+    let entries = entries.lock().await.clone();
+    let entries = entries.iter().rev().collect::<Vec<_>>();
+    assert_eq!(entries[0].message, "user 42 bought ABC-1");
+    assert_eq!(
+        entries[0].template.as_deref(),
+        Some("user {user} bought {item}")
+    );
+    assert_eq!(
+        entries[0].payload,
+        serde_json::json!({"user": 42, "item": "ABC-1"})
+    );
+    assert_eq!(entries[1].template, entries[0].template);
+    assert_eq!(
+        entries[1].payload,
+        serde_json::json!({"user": 7, "item": "XYZ-2", "store": "main"})
+    );
+    assert_eq!(entries[2].template.as_deref(), Some("user {} joined"));
+    assert_eq!(entries[2].payload, serde_json::Value::Null);
+    assert_eq!(entries[3].template, None);
+    assert_eq!(entries[4].message, "user 42 left");
+    assert_eq!(entries[4].payload, serde_json::json!({"id": 42}));
+}
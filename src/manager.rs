@@ -1,63 +1,951 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use flume::{Receiver, Sender};
 use futures::{future::BoxFuture, FutureExt};
 
-use crate::{backend::Backend, Log};
+use crate::backend::Backend;
+
+mod dispatcher;
+mod failure;
+mod filter;
+mod handle;
+mod stats;
+mod worker;
+
+use self::{
+    dispatcher::{Dispatcher, Spawner},
+    failure::FailureCallback,
+    handle::Shared,
+    worker::Batching,
+};
+pub use self::{
+    failure::{BackendFailure, FailurePolicy},
+    filter::Filter,
+    handle::{ManagerHandle, Overflow},
+    stats::{BackendStats, LevelCounts, ManagerStats},
+    worker::BackendLag,
+};
 
 /// A manager of log messages that runs asynchronously and forwards received log
 /// messages onto one or more backends
+///
+/// Each backend is processed in its own task with its own queue, so a slow
+/// backend only holds back the others once its queue is full. Each backend's
+/// queue has the same capacity as the `Manager`'s queue.
 #[derive(Default, Debug)]
 pub struct Manager {
-    backends: Vec<Box<dyn Backend>>,
+    backends: Vec<(Box<dyn Backend>, BackendOptions)>,
+    failure_callback: FailureCallback,
+    capacity: Option<usize>,
+    overflow: Overflow,
+    batching: Batching,
+    stats_interval: Option<Duration>,
+}
+
+/// Options controlling how a `Manager` treats an attached backend
+#[derive(Default, Debug)]
+pub struct BackendOptions {
+    /// What to do when the backend returns an error
+    pub failure_policy: FailurePolicy,
+    /// If present, only entries matching this filter are sent to the backend
+    pub filter: Option<Filter>,
+    /// The name used to identify the backend when it is removed or replaced
+    /// using a `ManagerHandle`
+    pub name: Option<String>,
+}
+
+impl BackendOptions {
+    /// Builder-style method to set `failure_policy`
+    #[must_use]
+    pub fn with_failure_policy(mut self, failure_policy: FailurePolicy) -> Self {
+        self.failure_policy = failure_policy;
+        self
+    }
+
+    /// Builder-style method to set `filter`
+    #[must_use]
+    pub fn with_filter<F: Into<Filter>>(mut self, filter: F) -> Self {
+        self.filter = Some(filter.into());
+        self
+    }
+
+    /// Builder-style method to set `name`
+    #[must_use]
+    pub fn with_name<N: Into<String>>(mut self, name: N) -> Self {
+        self.name = Some(name.into());
+        self
+    }
 }
 
 impl Manager {
     /// Attach a backend
-    pub fn with_backend<B: Backend + 'static>(mut self, backend: B) -> Self {
-        self.backends.push(Box::new(backend));
+    #[must_use]
+    pub fn with_backend<B: Backend + 'static>(self, backend: B) -> Self {
+        self.with_backend_options(backend, BackendOptions::default())
+    }
+
+    /// Attach a backend that only receives entries matching `filter`. Filters
+    /// are checked before any work is scheduled for the backend.
+    #[must_use]
+    pub fn with_backend_filtered<B: Backend + 'static, F: Into<Filter>>(
+        self,
+        backend: B,
+        filter: F,
+    ) -> Self {
+        self.with_backend_options(backend, BackendOptions::default().with_filter(filter))
+    }
+
+    /// Attach a backend with the provided `options`
+    #[must_use]
+    pub fn with_backend_options<B: Backend + 'static>(
+        mut self,
+        backend: B,
+        options: BackendOptions,
+    ) -> Self {
+        self.backends.push((Box::new(backend), options));
+        self
+    }
+
+    /// Sets the callback invoked whenever a backend reports a failure. By
+    /// default, failures are printed to stderr.
+    #[must_use]
+    pub fn with_failure_callback<F: Fn(&BackendFailure) + Send + Sync + 'static>(
+        mut self,
+        callback: F,
+    ) -> Self {
+        self.failure_callback = FailureCallback::new(callback);
+        self
+    }
+
+    /// Limits the number of entries waiting to be processed to `capacity`.
+    /// When the queue is full, `overflow` controls what happens to newly
+    /// submitted entries. Dropped entries are reported in a `Level::Warning`
+    /// entry once the queue has drained. By default, the queue is unbounded.
+    ///
+    /// `Overflow::Block` and `Overflow::DropBelow` block threads that submit
+    /// entries using `Log::submit`. Async code should use
+    /// `Log::submit_async` to wait for room instead.
+    #[must_use]
+    pub const fn with_capacity(mut self, capacity: usize, overflow: Overflow) -> Self {
+        self.capacity = Some(capacity);
+        self.overflow = overflow;
+        self
+    }
+
+    /// Controls how entries are batched before being passed to
+    /// `Backend::process_batch`. Each backend's batch is built from the
+    /// entries already waiting in its queue, up to `max_size` entries. If
+    /// `linger` is non-zero, the backend waits up to `linger` for more
+    /// entries to arrive before processing a batch that isn't full. By
+    /// default, batches contain up to 64 entries and do not linger.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_size` is zero.
+    #[must_use]
+    pub fn with_batching(mut self, max_size: usize, linger: Duration) -> Self {
+        assert!(max_size > 0, "batches must contain at least one entry");
+        self.batching = Batching { max_size, linger };
+        self
+    }
+
+    /// Periodically submits a `Level::Info` entry to the backends whose
+    /// payload contains the manager's `ManagerStats`. By default, statistics
+    /// are only available through `ManagerHandle::stats`.
+    #[must_use]
+    pub const fn with_stats_interval(mut self, interval: Duration) -> Self {
+        self.stats_interval = Some(interval);
         self
     }
 
     /// If you are using a custom async executor, this function allows you to
-    /// pass in a closure that is responsible for spawning the future into your
-    /// async executor.
+    /// pass in a closure that is responsible for spawning futures into your
+    /// async executor. The closure is called once for the `Manager` and once
+    /// for each backend.
     ///
     /// # Returns
     ///
-    /// The destination for the Manager. This is passed in during creation of a `Configuration`
+    /// The handle for the Manager. This is passed in during creation of a `Configuration`
     #[must_use]
-    pub fn launch<F: FnOnce(BoxFuture<'static, ()>)>(self, spawner: F) -> Sender<Arc<Log>> {
-        let (sender, receiver) = flume::unbounded();
+    pub fn launch<F: Fn(BoxFuture<'static, ()>) + Send + Sync + 'static>(
+        self,
+        spawner: F,
+    ) -> ManagerHandle {
+        let spawner = Spawner(Arc::new(spawner));
+        let (entries, queue) = channel(self.capacity);
+        let (commands, command_receiver) = flume::unbounded();
+        let shared = Arc::<Shared>::default();
+        let evictor = match (self.capacity, self.overflow) {
+            (Some(_), Overflow::DropOldest) => Some(queue.clone()),
+            _ => None,
+        };
 
-        spawner(self.run(receiver).boxed());
+        let mut dispatcher = Dispatcher::new(
+            spawner.clone(),
+            self.capacity,
+            self.batching,
+            self.stats_interval,
+            self.failure_callback,
+            shared.clone(),
+        );
+        for (backend, options) in self.backends {
+            dispatcher.attach(backend, options);
+        }
+        spawner.spawn(dispatcher.run(queue, command_receiver).boxed());
 
-        sender
+        ManagerHandle {
+            entries,
+            evictor,
+            commands,
+            overflow: self.overflow,
+            shared,
+        }
     }
 
     /// Spawns this manager within the global tokio runtime.
     ///
     /// # Returns
     ///
-    /// The destination for the Manager. This is passed in during creation of a `Configuration`
+    /// The handle for the Manager. This is passed in during creation of a `Configuration`
+    #[cfg(feature = "tokio")]
     #[must_use]
-    pub fn spawn_tokio(self) -> Sender<Arc<Log>> {
+    pub fn spawn_tokio(self) -> ManagerHandle {
         self.launch(|task| {
             tokio::spawn(task);
         })
     }
+}
+
+fn channel<T>(capacity: Option<usize>) -> (Sender<T>, Receiver<T>) {
+    match capacity {
+        Some(capacity) => flume::bounded(capacity),
+        None => flume::unbounded(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::VecDeque,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::{
+        backend::{BatchError, Memory},
+        Configuration, Level, Log,
+    };
+
+    #[derive(Debug, Default)]
+    struct ShutdownCounter {
+        processed: Arc<AtomicUsize>,
+        shutdowns: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Backend for ShutdownCounter {
+        async fn process_log(&mut self, _log: &Log) -> anyhow::Result<()> {
+            self.processed.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        async fn shutdown(&mut self) -> anyhow::Result<()> {
+            self.shutdowns.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    /// Records each lifecycle event it receives
+    #[derive(Debug, Default)]
+    struct LifecycleRecorder {
+        events: Arc<std::sync::Mutex<Vec<&'static str>>>,
+    }
+
+    #[async_trait]
+    impl Backend for LifecycleRecorder {
+        async fn init(&mut self) -> anyhow::Result<()> {
+            self.events.lock().unwrap().push("init");
+            Ok(())
+        }
+
+        async fn process_log(&mut self, _log: &Log) -> anyhow::Result<()> {
+            self.events.lock().unwrap().push("log");
+            Ok(())
+        }
+
+        async fn flush(&mut self) -> anyhow::Result<()> {
+            self.events.lock().unwrap().push("flush");
+            Ok(())
+        }
+
+        async fn shutdown(&mut self) -> anyhow::Result<()> {
+            self.events.lock().unwrap().push("shutdown");
+            Ok(())
+        }
+    }
+
+    /// Fails the first `failures` attempts, then succeeds
+    #[derive(Debug, Default)]
+    struct Flaky {
+        failures: usize,
+        attempts: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Backend for Flaky {
+        async fn process_log(&mut self, _log: &Log) -> anyhow::Result<()> {
+            if self.attempts.fetch_add(1, Ordering::SeqCst) < self.failures {
+                anyhow::bail!("flaky failure")
+            }
+            Ok(())
+        }
+    }
+
+    /// Records the size of each batch it processes
+    #[derive(Debug, Default)]
+    struct BatchRecorder {
+        batches: Arc<std::sync::Mutex<Vec<usize>>>,
+    }
+
+    #[async_trait]
+    impl Backend for BatchRecorder {
+        async fn process_log(&mut self, _log: &Log) -> anyhow::Result<()> {
+            unreachable!("process_batch is overridden")
+        }
+
+        async fn process_batch(&mut self, logs: &[Arc<Log>]) -> Result<(), BatchError> {
+            self.batches.lock().unwrap().push(logs.len());
+            Ok(())
+        }
+    }
+
+    /// Submits `count` entries and waits for them to be processed
+    async fn submit_entries(handle: &ManagerHandle, count: usize) {
+        Configuration::named("failure_tests", handle.clone())
+            .run(async {
+                for _ in 0..count {
+                    Log::info("A").submit();
+                }
+            })
+            .await;
+        handle.flush().await;
+    }
+
+    fn failure_counter() -> (Arc<AtomicUsize>, impl Fn(&BackendFailure) + Send + Sync) {
+        let failures = Arc::new(AtomicUsize::default());
+        let counter = failures.clone();
+        (failures, move |failure: &BackendFailure| {
+            assert!(!failure.entries.is_empty());
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+    }
 
-    async fn run(mut self, receiver: Receiver<Arc<Log>>) {
-        while let Ok(log) = receiver.recv_async().await {
-            futures::future::join_all(
-                self.backends
-                    .iter_mut()
-                    .map(|backend| backend.process_log(&log)),
+    /// Returns the messages of the entries a `Memory` backend received, oldest
+    /// first
+    fn messages(entries: &VecDeque<Log>) -> Vec<String> {
+        entries
+            .iter()
+            .rev()
+            .map(|log| log.message.clone())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn ignore_failure_test() {
+        let backend = Flaky {
+            failures: 2,
+            ..Flaky::default()
+        };
+        let attempts = backend.attempts.clone();
+        let (failures, callback) = failure_counter();
+        let handle = Manager::default()
+            .with_backend(backend)
+            .with_failure_callback(callback)
+            .launch(|task| {
+                tokio::spawn(task);
+            });
+
+        submit_entries(&handle, 3).await;
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        assert_eq!(failures.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn retry_failure_test() {
+        let backend = Flaky {
+            failures: 2,
+            ..Flaky::default()
+        };
+        let attempts = backend.attempts.clone();
+        let (failures, callback) = failure_counter();
+        let handle = Manager::default()
+            .with_backend_options(
+                backend,
+                BackendOptions::default().with_failure_policy(FailurePolicy::Retry {
+                    attempts: 2,
+                    backoff: Duration::from_millis(1),
+                }),
             )
-            .await
-            .into_iter()
-            .collect::<Result<Vec<_>, anyhow::Error>>()
-            .expect("Error communicating with logging backends");
+            .with_failure_callback(callback)
+            .launch(|task| {
+                tokio::spawn(task);
+            });
+
+        submit_entries(&handle, 1).await;
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        assert_eq!(failures.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn disable_failure_test() {
+        let backend = Flaky {
+            failures: usize::MAX,
+            ..Flaky::default()
+        };
+        let attempts = backend.attempts.clone();
+        let disabled = Arc::new(AtomicUsize::default());
+        let disabled_counter = disabled.clone();
+        let handle = Manager::default()
+            .with_backend_options(
+                backend,
+                BackendOptions::default().with_failure_policy(FailurePolicy::DisableAfter(2)),
+            )
+            .with_failure_callback(move |failure| {
+                if failure.disabled {
+                    disabled_counter.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .launch(|task| {
+                tokio::spawn(task);
+            });
+
+        submit_entries(&handle, 4).await;
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+        assert_eq!(disabled.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn fallback_failure_test() {
+        let fallback = Memory::new(10);
+        let entries = fallback.entries.clone();
+        let (failures, callback) = failure_counter();
+        let handle = Manager::default()
+            .with_backend_options(
+                Flaky {
+                    failures: 1,
+                    ..Flaky::default()
+                },
+                BackendOptions::default()
+                    .with_failure_policy(FailurePolicy::Fallback(Box::new(fallback))),
+            )
+            .with_failure_callback(callback)
+            .launch(|task| {
+                tokio::spawn(task);
+            });
+
+        submit_entries(&handle, 2).await;
+        assert_eq!(failures.load(Ordering::SeqCst), 1);
+        assert_eq!(entries.lock().await.len(), 1);
+    }
+
+    /// Fails every entry whose message is "B", recording the messages of the
+    /// entries it processes
+    #[derive(Debug, Default)]
+    struct FailsB {
+        delivered: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl Backend for FailsB {
+        async fn process_log(&mut self, log: &Log) -> anyhow::Result<()> {
+            anyhow::ensure!(log.message != "B", "B always fails");
+            self.delivered.lock().unwrap().push(log.message.clone());
+            Ok(())
         }
     }
+
+    #[tokio::test]
+    async fn partial_batch_failure_test() {
+        let policies = [
+            FailurePolicy::Ignore,
+            FailurePolicy::Retry {
+                attempts: 2,
+                backoff: Duration::from_millis(1),
+            },
+        ];
+        for policy in policies {
+            let backend = FailsB::default();
+            let delivered = backend.delivered.clone();
+            let (failures, callback) = failure_counter();
+            let handle = Manager::default()
+                .with_backend_options(
+                    backend,
+                    BackendOptions::default().with_failure_policy(policy),
+                )
+                .with_failure_callback(callback)
+                .launch(|task| {
+                    tokio::spawn(task);
+                });
+
+            Configuration::named("partial_batch_failure_test", handle.clone())
+                .run(async {
+                    for message in ["A", "B", "C", "D", "E"] {
+                        Log::info(message).submit();
+                    }
+                })
+                .await;
+            handle.flush().await;
+
+            // Only the failing entry is lost, and entries are never delivered
+            // twice, even when the batch is retried.
+            assert_eq!(&*delivered.lock().unwrap(), &["A", "C", "D", "E"]);
+            assert_eq!(failures.load(Ordering::SeqCst), 1);
+            let stats = handle.stats();
+            assert_eq!(stats.backends[0].succeeded, 4);
+            assert_eq!(stats.backends[0].failed, 1);
+        }
+    }
+
+    /// Waits for permission before processing each entry
+    #[derive(Debug)]
+    struct Gated {
+        started: flume::Sender<()>,
+        release: flume::Receiver<()>,
+    }
+
+    #[async_trait]
+    impl Backend for Gated {
+        async fn process_log(&mut self, _log: &Log) -> anyhow::Result<()> {
+            self.started.send(())?;
+            self.release.recv_async().await?;
+            Ok(())
+        }
+    }
+
+    /// Submits six entries to a manager with a capacity of two while the first
+    /// entry is stuck being processed, returning the messages that were
+    /// delivered, oldest first.
+    async fn overflow_test(overflow: Overflow) -> Vec<String> {
+        let (started, started_receiver) = flume::unbounded();
+        let (releaser, release) = flume::unbounded();
+        let memory = Memory::new(10);
+        let entries = memory.entries.clone();
+        let handle = Manager::default()
+            .with_backend(Gated { started, release })
+            .with_backend(memory)
+            .with_capacity(2, overflow)
+            .launch(|task| {
+                tokio::spawn(task);
+            });
+
+        Configuration::named("overflow_test", handle.clone())
+            .run(async {
+                Log::info("0").submit();
+                started_receiver.recv_async().await.unwrap();
+                for i in 1..6 {
+                    Log::info(i).submit();
+                }
+            })
+            .await;
+        for _ in 0..10 {
+            releaser.send(()).unwrap();
+        }
+        handle.flush().await;
+
+        let entries = entries.lock().await;
+        messages(&entries)
+    }
+
+    #[tokio::test]
+    async fn drop_newest_test() {
+        assert_eq!(
+            overflow_test(Overflow::DropNewest).await,
+            vec!["0", "1", "2", "3 entries dropped"]
+        );
+    }
+
+    #[tokio::test]
+    async fn drop_oldest_test() {
+        assert_eq!(
+            overflow_test(Overflow::DropOldest).await,
+            vec!["0", "4", "5", "3 entries dropped"]
+        );
+    }
+
+    #[tokio::test]
+    async fn block_test() {
+        let (started, started_receiver) = flume::unbounded();
+        let (releaser, release) = flume::unbounded();
+        let memory = Memory::new(10);
+        let entries = memory.entries.clone();
+        let handle = Manager::default()
+            .with_backend(Gated { started, release })
+            .with_backend(memory)
+            .with_capacity(2, Overflow::Block)
+            .launch(|task| {
+                tokio::spawn(task);
+            });
+
+        Configuration::named("block_test", handle.clone())
+            .run(async {
+                Log::info("0").submit_async().await;
+                started_receiver.recv_async().await.unwrap();
+                // This only runs once a submission waits for room, which
+                // must not block the thread the manager runs on
+                tokio::spawn(async move {
+                    for _ in 0..6 {
+                        releaser.send_async(()).await.unwrap();
+                    }
+                });
+                for i in 1..6 {
+                    Log::info(i).submit_async().await;
+                }
+            })
+            .await;
+        handle.flush().await;
+
+        assert_eq!(
+            messages(&*entries.lock().await),
+            vec!["0", "1", "2", "3", "4", "5"]
+        );
+        assert_eq!(handle.stats().dropped, 0);
+    }
+
+    #[tokio::test]
+    async fn drop_below_test() {
+        assert_eq!(
+            overflow_test(Overflow::DropBelow(Level::Warning)).await,
+            vec!["0", "1", "2", "3 entries dropped"]
+        );
+    }
+
+    #[tokio::test]
+    async fn independent_backends_test() {
+        let (started, started_receiver) = flume::unbounded();
+        let (releaser, release) = flume::unbounded();
+        let memory = Memory::new(10);
+        let entries = memory.entries.clone();
+        let handle = Manager::default()
+            .with_backend(Gated { started, release })
+            .with_backend(memory)
+            .launch(|task| {
+                tokio::spawn(task);
+            });
+
+        Configuration::named("independent_backends_test", handle.clone())
+            .run(async {
+                for i in 0..5 {
+                    Log::info(i).submit();
+                }
+            })
+            .await;
+
+        // The memory backend receives every entry while the gated backend is
+        // still stuck on the first one
+        started_receiver.recv_async().await.unwrap();
+        while entries.lock().await.len() < 5 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(handle.lag()[0].pending, 5);
+
+        for _ in 0..5 {
+            releaser.send(()).unwrap();
+        }
+        handle.flush().await;
+        assert!(handle.lag().iter().all(|lag| lag.pending == 0));
+    }
+
+    #[tokio::test]
+    async fn filter_test() {
+        let trace = Memory::new(10);
+        let trace_entries = trace.entries.clone();
+        let info = Memory::new(10);
+        let info_entries = info.entries.clone();
+        let tagged = Memory::new(10);
+        let tagged_entries = tagged.entries.clone();
+        let custom = Memory::new(10);
+        let custom_entries = custom.entries.clone();
+        let handle = Manager::default()
+            .with_backend(trace)
+            .with_backend_filtered(info, Level::Info)
+            .with_backend_filtered(tagged, Filter::payload("alert", |value| value == true))
+            .with_backend_filtered(custom, Filter::custom(|log| log.message.starts_with('B')))
+            .launch(|task| {
+                tokio::spawn(task);
+            });
+
+        Configuration::named("filter_test", handle.clone())
+            .run(async {
+                Log::trace("A").submit();
+                Log::info("B").submit();
+                Log::error("C").with("alert", true).unwrap().submit();
+                Log::error("D").with("alert", false).unwrap().submit();
+            })
+            .await;
+        handle.flush().await;
+
+        assert_eq!(
+            messages(&*trace_entries.lock().await),
+            vec!["A", "B", "C", "D"]
+        );
+        assert_eq!(messages(&*info_entries.lock().await), vec!["B", "C", "D"]);
+        assert_eq!(messages(&*tagged_entries.lock().await), vec!["C"]);
+        assert_eq!(messages(&*custom_entries.lock().await), vec!["B"]);
+        assert_eq!(handle.lag().iter().filter(|lag| lag.pending > 0).count(), 0);
+    }
+
+    #[tokio::test]
+    async fn event_filter_test() {
+        #[derive(serde::Serialize)]
+        enum TestEvent {
+            UserJoined { id: u64 },
+            UserLeft { id: u64 },
+        }
+
+        let joined = Memory::new(10);
+        let joined_entries = joined.entries.clone();
+        let handle = Manager::default()
+            .with_backend_filtered(joined, Filter::Event(String::from("UserJoined")))
+            .launch(|task| {
+                tokio::spawn(task);
+            });
+
+        Configuration::named("event_filter_test", handle.clone())
+            .run(async {
+                Log::event(Level::Info, &TestEvent::UserJoined { id: 1 }).submit();
+                Log::event(Level::Info, &TestEvent::UserLeft { id: 1 }).submit();
+                Log::info("UserJoined").submit();
+            })
+            .await;
+        handle.flush().await;
+
+        let entries = joined_entries.lock().await.clone();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].message, "UserJoined");
+        assert_eq!(
+            entries[0].event.as_ref().unwrap().data,
+            serde_json::json!({"id": 1})
+        );
+    }
+
+    #[tokio::test]
+    async fn batching_test() {
+        let backend = BatchRecorder::default();
+        let batches = backend.batches.clone();
+        let handle = Manager::default()
+            .with_backend(backend)
+            .with_batching(4, Duration::default())
+            .launch(|task| {
+                tokio::spawn(task);
+            });
+
+        submit_entries(&handle, 10).await;
+        let batches = batches.lock().unwrap().clone();
+        assert_eq!(batches.iter().sum::<usize>(), 10);
+        assert!(batches.iter().all(|&size| size <= 4));
+        assert!(batches.iter().any(|&size| size > 1));
+    }
+
+    #[tokio::test]
+    async fn batching_linger_test() {
+        let backend = BatchRecorder::default();
+        let batches = backend.batches.clone();
+        let handle = Manager::default()
+            .with_backend(backend)
+            .with_batching(10, Duration::from_millis(50))
+            .launch(|task| {
+                tokio::spawn(task);
+            });
+
+        Configuration::named("batching_linger_test", handle.clone())
+            .run(async {
+                Log::info("A").submit();
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                Log::info("B").submit();
+            })
+            .await;
+        handle.flush().await;
+        assert_eq!(&*batches.lock().unwrap(), &[2]);
+    }
+
+    #[tokio::test]
+    async fn lifecycle_test() {
+        let backend = LifecycleRecorder::default();
+        let events = backend.events.clone();
+        let handle = Manager::default().with_backend(backend).launch(|task| {
+            tokio::spawn(task);
+        });
+
+        handle.flush().await;
+        assert_eq!(&*events.lock().unwrap(), &["init", "flush"]);
+
+        submit_entries(&handle, 2).await;
+        {
+            let events = events.lock().unwrap().clone();
+            assert_eq!(events.iter().filter(|&&event| event == "log").count(), 2);
+            assert_eq!(events.last(), Some(&"flush"));
+        }
+
+        handle.shutdown().await;
+        assert!(events.lock().unwrap().ends_with(&["flush", "shutdown"]));
+    }
+
+    #[tokio::test]
+    async fn runtime_backends_test() {
+        let original = Memory::new(10);
+        let original_entries = original.entries.clone();
+        let capture = Memory::new(10);
+        let capture_entries = capture.entries.clone();
+        let replacement = Memory::new(10);
+        let replacement_entries = replacement.entries.clone();
+        let handle = Manager::default()
+            .with_backend_options(original, BackendOptions::default().with_name("memory"))
+            .launch(|task| {
+                tokio::spawn(task);
+            });
+
+        Configuration::named("runtime_backends_test", handle.clone())
+            .run(async {
+                Log::info("A").submit();
+                assert!(handle.add_backend("capture", capture).await);
+                assert!(!handle.add_backend("capture", Memory::new(1)).await);
+                Log::info("B").submit();
+
+                assert!(handle.replace_backend("memory", replacement).await);
+                assert_eq!(messages(&*original_entries.lock().await), vec!["A", "B"]);
+                Log::info("C").submit();
+
+                assert!(handle.remove_backend("capture").await);
+                assert!(!handle.remove_backend("capture").await);
+                assert!(!handle.replace_backend("capture", Memory::new(1)).await);
+                Log::info("D").submit();
+            })
+            .await;
+        handle.flush().await;
+
+        assert_eq!(messages(&*original_entries.lock().await), vec!["A", "B"]);
+        assert_eq!(messages(&*capture_entries.lock().await), vec!["B", "C"]);
+        assert_eq!(messages(&*replacement_entries.lock().await), vec!["C", "D"]);
+        let lag = handle.lag();
+        assert_eq!(lag.len(), 1);
+        assert_eq!(lag[0].name.as_deref(), Some("memory"));
+    }
+
+    #[tokio::test]
+    async fn stats_test() {
+        let handle = Manager::default()
+            .with_backend_options(
+                Memory::new(10),
+                BackendOptions::default().with_name("memory"),
+            )
+            .with_backend(Flaky {
+                failures: 1,
+                ..Flaky::default()
+            })
+            .with_failure_callback(|_| {})
+            .launch(|task| {
+                tokio::spawn(task);
+            });
+
+        Configuration::named("stats_test", handle.clone())
+            .run(async {
+                Log::info("A").submit();
+                Log::info("B").submit();
+                Log::error("C").submit();
+            })
+            .await;
+        handle.flush().await;
+
+        let stats = handle.stats();
+        assert_eq!(stats.submitted.get(Level::Info), 2);
+        assert_eq!(stats.submitted.get(Level::Error), 1);
+        assert_eq!(stats.submitted.total(), 3);
+        assert_eq!(stats.dropped, 0);
+        assert_eq!(stats.queue_depth, 0);
+        assert_eq!(stats.backends.len(), 2);
+        assert_eq!(stats.backends[0].name.as_deref(), Some("memory"));
+        assert_eq!(stats.backends[0].succeeded, 3);
+        assert_eq!(stats.backends[0].failed, 0);
+        assert_eq!(stats.backends[1].succeeded, 2);
+        assert_eq!(stats.backends[1].failed, 1);
+        assert!(stats.backends.iter().all(|backend| backend.pending == 0));
+    }
+
+    #[tokio::test]
+    async fn stats_interval_test() {
+        let memory = Memory::new(10);
+        let entries = memory.entries.clone();
+        let handle = Manager::default()
+            .with_backend(memory)
+            .with_stats_interval(Duration::from_millis(1))
+            .launch(|task| {
+                tokio::spawn(task);
+            });
+
+        while entries.lock().await.is_empty() {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        let entries = entries.lock().await;
+        let report = entries.back().unwrap();
+        assert_eq!(report.level, Level::Info);
+        assert_eq!(report.process, "sirlog");
+        assert_eq!(report.message, "manager statistics");
+        assert_eq!(report.payload["submitted"]["info"], 0);
+        assert_eq!(report.payload["backends"][0]["failed"], 0);
+        drop(entries);
+        handle.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn flush_test() {
+        let test_backend = Memory::new(10);
+        let entries = test_backend.entries.clone();
+        let handle = Manager::default()
+            .with_backend(test_backend)
+            .launch(|task| {
+                tokio::spawn(task);
+            });
+
+        Configuration::named("flush_test", handle.clone())
+            .run(async {
+                for _ in 0..5 {
+                    Log::info("A").submit();
+                }
+            })
+            .await;
+
+        handle.flush().await;
+        assert_eq!(entries.lock().await.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn shutdown_test() {
+        let backend = ShutdownCounter::default();
+        let processed = backend.processed.clone();
+        let shutdowns = backend.shutdowns.clone();
+        let handle = Manager::default().with_backend(backend).launch(|task| {
+            tokio::spawn(task);
+        });
+
+        Configuration::named("shutdown_test", handle.clone())
+            .run(async {
+                for _ in 0..5 {
+                    Log::info("A").submit();
+                }
+            })
+            .await;
+
+        handle.shutdown().await;
+        assert_eq!(processed.load(Ordering::SeqCst), 5);
+        assert_eq!(shutdowns.load(Ordering::SeqCst), 1);
+
+        // Once stopped, flushing and shutting down again resolve immediately
+        handle.flush().await;
+        handle.shutdown().await;
+        assert_eq!(shutdowns.load(Ordering::SeqCst), 1);
+    }
 }
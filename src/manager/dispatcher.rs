@@ -0,0 +1,331 @@
+use std::{
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
+
+use chrono::Utc;
+use flume::{Receiver, Sender};
+use futures::{
+    future::{BoxFuture, Fuse},
+    FutureExt,
+};
+use futures_timer::Delay;
+
+use super::{
+    failure::FailureCallback,
+    handle::{Command, Request, Shared},
+    worker::{Batching, Worker, WorkerMessage, WorkerStatus},
+    BackendOptions, Filter,
+};
+use crate::{backend::Backend, Level, Log};
+
+/// Spawns futures into the async executor a `Manager` was launched with
+#[derive(Clone)]
+pub struct Spawner(pub Arc<dyn Fn(BoxFuture<'static, ()>) + Send + Sync>);
+
+impl Spawner {
+    pub fn spawn(&self, future: BoxFuture<'static, ()>) {
+        (self.0)(future);
+    }
+}
+
+/// The sending side of a launched `Worker`'s queue
+#[derive(Debug)]
+struct WorkerHandle {
+    queue: Sender<WorkerMessage>,
+    status: Arc<WorkerStatus>,
+    filter: Option<Filter>,
+}
+
+impl WorkerHandle {
+    async fn request<F: FnOnce(Sender<()>) -> WorkerMessage>(&self, message: F) -> Receiver<()> {
+        let (sender, receiver) = flume::bounded(1);
+        let _ = self.queue.send_async(message(sender)).await;
+        receiver
+    }
+}
+
+/// Receives entries submitted to a `Manager` and forwards them to each backend
+/// worker
+pub struct Dispatcher {
+    workers: Vec<WorkerHandle>,
+    spawner: Spawner,
+    capacity: Option<usize>,
+    batching: Batching,
+    stats_interval: Option<Duration>,
+    failure_callback: FailureCallback,
+    shared: Arc<Shared>,
+    next_index: usize,
+}
+
+impl Dispatcher {
+    pub const fn new(
+        spawner: Spawner,
+        capacity: Option<usize>,
+        batching: Batching,
+        stats_interval: Option<Duration>,
+        failure_callback: FailureCallback,
+        shared: Arc<Shared>,
+    ) -> Self {
+        Self {
+            workers: Vec::new(),
+            spawner,
+            capacity,
+            batching,
+            stats_interval,
+            failure_callback,
+            shared,
+            next_index: 0,
+        }
+    }
+
+    /// Spawns a worker for `backend` and starts dispatching entries to it
+    pub fn attach(&mut self, backend: Box<dyn Backend>, options: BackendOptions) {
+        let worker = self.launch_worker(backend, options);
+        self.workers.push(worker);
+        self.publish_statuses();
+    }
+
+    fn launch_worker(
+        &mut self,
+        backend: Box<dyn Backend>,
+        options: BackendOptions,
+    ) -> WorkerHandle {
+        let mut worker = Worker::new(self.next_index, backend, options);
+        self.next_index += 1;
+
+        let (sender, receiver) = super::channel(self.capacity);
+        let status = worker.status();
+        let filter = worker.take_filter();
+        self.spawner.spawn(
+            worker
+                .run(receiver, self.batching, self.failure_callback.clone())
+                .boxed(),
+        );
+
+        WorkerHandle {
+            queue: sender,
+            status,
+            filter,
+        }
+    }
+
+    fn publish_statuses(&self) {
+        let mut statuses = self.shared.workers.write().unwrap();
+        statuses.clear();
+        statuses.extend(self.workers.iter().map(|worker| worker.status.clone()));
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.workers
+            .iter()
+            .position(|worker| worker.status.name() == Some(name))
+    }
+
+    pub async fn run(mut self, queue: Receiver<Arc<Log>>, commands: Receiver<Command>) {
+        let mut received = 0_u64;
+        let mut pending_requests = Vec::new();
+        let mut stats_timer = self.stats_timer();
+        loop {
+            futures::select_biased! {
+                command = commands.recv_async() => match command {
+                    Ok(Command::Sequenced { through, request }) => {
+                        pending_requests.push((through, request));
+                    }
+                    Ok(Command::Shutdown(reply)) => {
+                        pending_requests.push((u64::MAX, Request::Flush(reply)));
+                        break;
+                    }
+                    // Every handle has been dropped
+                    Err(_) => break,
+                },
+                log = queue.recv_async() => match log {
+                    Ok(log) => {
+                        received += 1;
+                        self.dispatch(&log).await;
+                        if queue.is_empty() {
+                            self.report_dropped().await;
+                        }
+                    }
+                    Err(_) => break,
+                },
+                () = stats_timer => {
+                    self.report_stats(queue.len()).await;
+                    stats_timer = self.stats_timer();
+                }
+            }
+
+            let processed = received + self.shared.evicted.load(Ordering::SeqCst);
+            let (ready, pending) = pending_requests
+                .into_iter()
+                .partition(|(through, _)| *through <= processed);
+            pending_requests = pending;
+            for (_, request) in ready {
+                self.handle_request(request).await;
+            }
+        }
+
+        // Drain everything that was queued before the manager was asked to
+        // stop, answering any outstanding flushes once the backends have been
+        // finalized. Outstanding changes to the attached backends are dropped,
+        // which is reported to their callers as a failure.
+        while let Ok(log) = queue.try_recv() {
+            self.dispatch(&log).await;
+        }
+        self.report_dropped().await;
+        while let Ok(command) = commands.try_recv() {
+            match command {
+                Command::Sequenced { through, request } => {
+                    pending_requests.push((through, request));
+                }
+                Command::Shutdown(reply) => {
+                    pending_requests.push((u64::MAX, Request::Flush(reply)));
+                }
+            }
+        }
+
+        let mut acknowledgements = Vec::with_capacity(self.workers.len());
+        for worker in &self.workers {
+            acknowledgements.push(worker.request(WorkerMessage::Shutdown).await);
+        }
+        for acknowledgement in acknowledgements {
+            let _ = acknowledgement.recv_async().await;
+        }
+
+        for (_, request) in pending_requests {
+            if let Request::Flush(reply) = request {
+                let _ = reply.send(());
+            }
+        }
+    }
+
+    async fn handle_request(&mut self, request: Request) {
+        match request {
+            Request::Flush(reply) => self.flush_workers(reply).await,
+            Request::Attach {
+                name,
+                backend,
+                mut options,
+                replace,
+                reply,
+            } => match (self.position(&name), replace) {
+                (None, false) => {
+                    options.name = Some(name);
+                    self.attach(backend, options);
+                    let _ = reply.send(true);
+                }
+                (Some(index), true) => {
+                    options.name = Some(name);
+                    let worker = self.launch_worker(backend, options);
+                    let previous = std::mem::replace(&mut self.workers[index], worker);
+                    self.publish_statuses();
+                    self.retire(previous, reply).await;
+                }
+                _ => {
+                    let _ = reply.send(false);
+                }
+            },
+            Request::Detach { name, reply } => match self.position(&name) {
+                Some(index) => {
+                    let previous = self.workers.remove(index);
+                    self.publish_statuses();
+                    self.retire(previous, reply).await;
+                }
+                None => {
+                    let _ = reply.send(false);
+                }
+            },
+        }
+    }
+
+    async fn dispatch(&self, log: &Arc<Log>) {
+        for worker in &self.workers {
+            if let Some(filter) = &worker.filter {
+                if !filter.matches(log) {
+                    continue;
+                }
+            }
+
+            worker.status.queued();
+            let _ = worker
+                .queue
+                .send_async(WorkerMessage::Log(log.clone()))
+                .await;
+        }
+    }
+
+    /// Replies to `reply` once every backend has processed the entries
+    /// dispatched to it so far, without blocking further dispatching.
+    async fn flush_workers(&self, reply: Sender<()>) {
+        let mut acknowledgements = Vec::with_capacity(self.workers.len());
+        for worker in &self.workers {
+            acknowledgements.push(worker.request(WorkerMessage::Flush).await);
+        }
+        self.spawner.spawn(
+            async move {
+                for acknowledgement in acknowledgements {
+                    let _ = acknowledgement.recv_async().await;
+                }
+                let _ = reply.send(());
+            }
+            .boxed(),
+        );
+    }
+
+    /// Shuts down a worker that is no longer receiving entries, replying to
+    /// `reply` once it has processed everything that was dispatched to it.
+    async fn retire(&self, worker: WorkerHandle, reply: Sender<bool>) {
+        let acknowledgement = worker.request(WorkerMessage::Shutdown).await;
+        self.spawner.spawn(
+            async move {
+                let _ = acknowledgement.recv_async().await;
+                let _ = reply.send(true);
+            }
+            .boxed(),
+        );
+    }
+
+    /// Submits a synthetic entry to the backends if any entries have been
+    /// dropped since the last report
+    async fn report_dropped(&self) {
+        let dropped = self.shared.dropped.swap(0, Ordering::SeqCst);
+        if dropped > 0 {
+            self.report(
+                Level::Warning,
+                format!("{dropped} entries dropped"),
+                serde_json::json!({ "dropped": dropped }),
+            )
+            .await;
+        }
+    }
+
+    /// Returns a timer that completes when the next statistics report is due,
+    /// or never if periodic reports are disabled
+    fn stats_timer(&self) -> Fuse<Delay> {
+        self.stats_interval
+            .map_or_else(Fuse::terminated, |interval| Delay::new(interval).fuse())
+    }
+
+    async fn report_stats(&self, queue_depth: usize) {
+        let stats = self.shared.stats(queue_depth);
+        let payload = serde_json::to_value(&stats).expect("stats are always serializable");
+        self.report(Level::Info, String::from("manager statistics"), payload)
+            .await;
+    }
+
+    /// Submits a synthetic entry created by the manager itself to the backends
+    async fn report(&self, level: Level, message: String, payload: serde_json::Value) {
+        let log = Arc::new(Log {
+            level,
+            process: String::from("sirlog"),
+            message,
+            timestamp: Utc::now(),
+            payload,
+            location: None,
+            template: None,
+            event: None,
+            inherited: Vec::new(),
+        });
+        self.dispatch(&log).await;
+    }
+}
@@ -0,0 +1,80 @@
+use std::{fmt::Debug, sync::Arc, time::Duration};
+
+use crate::{backend::Backend, Log};
+
+/// Controls what a `Manager` does when a backend returns an error
+#[derive(Default, Debug)]
+pub enum FailurePolicy {
+    /// Report the failure and continue with the next entry
+    #[default]
+    Ignore,
+    /// Retry the failed operation, waiting `backoff` before the first retry
+    /// and doubling the wait after each subsequent failure
+    Retry {
+        /// The maximum number of retries before the failure is reported
+        attempts: u32,
+        /// The delay before the first retry
+        backoff: Duration,
+    },
+    /// Stop sending entries to the backend once it has failed this many times
+    /// in a row
+    DisableAfter(u32),
+    /// Hand entries that fail to another backend
+    Fallback(Box<dyn Backend>),
+}
+
+/// A failure reported by a backend attached to a `Manager`
+#[derive(Debug)]
+pub struct BackendFailure {
+    /// The index of the backend, in the order it was attached
+    pub backend: usize,
+    /// The name of the backend, if it was given one
+    pub name: Option<String>,
+    /// The entries being processed when the failure happened. This is empty
+    /// if the failure happened while shutting down.
+    pub entries: Vec<Arc<Log>>,
+    /// The error returned by the backend
+    pub error: anyhow::Error,
+    /// Whether the backend has been disabled as a result of this failure
+    pub disabled: bool,
+}
+
+/// The callback invoked for each `BackendFailure`
+#[derive(Clone)]
+pub struct FailureCallback(Arc<dyn Fn(&BackendFailure) + Send + Sync>);
+
+impl FailureCallback {
+    pub fn new<F: Fn(&BackendFailure) + Send + Sync + 'static>(callback: F) -> Self {
+        Self(Arc::new(callback))
+    }
+
+    pub fn report(&self, failure: &BackendFailure) {
+        (self.0)(failure);
+    }
+}
+
+impl Default for FailureCallback {
+    fn default() -> Self {
+        Self::new(|failure| {
+            eprintln!(
+                "sirlog: backend {} failed{}: {:?}",
+                failure
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| failure.backend.to_string()),
+                if failure.disabled {
+                    " and was disabled"
+                } else {
+                    ""
+                },
+                failure.error
+            );
+        })
+    }
+}
+
+impl Debug for FailureCallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FailureCallback").finish()
+    }
+}
@@ -0,0 +1,75 @@
+use std::{fmt::Debug, sync::Arc};
+
+use crate::{Level, Log};
+
+/// Decides which entries a `Manager` sends to a backend
+#[derive(Clone)]
+pub enum Filter {
+    /// Only entries with this level or a higher severity
+    MinimumLevel(Level),
+    /// Only entries created by this process
+    Process(String),
+    /// Only entries created by `Log::event()` from an enum variant with this
+    /// name
+    Event(String),
+    /// Only entries whose payload contains `key` with a value matching
+    /// `predicate`
+    Payload {
+        /// The payload key to examine
+        key: String,
+        /// Returns true if the value of `key` matches
+        predicate: Arc<dyn Fn(&serde_json::Value) -> bool + Send + Sync>,
+    },
+    /// Only entries for which the closure returns true
+    Custom(Arc<dyn Fn(&Log) -> bool + Send + Sync>),
+}
+
+impl Filter {
+    /// Creates a `Filter::Payload` from `key` and `predicate`
+    pub fn payload<K: Into<String>, F: Fn(&serde_json::Value) -> bool + Send + Sync + 'static>(
+        key: K,
+        predicate: F,
+    ) -> Self {
+        Self::Payload {
+            key: key.into(),
+            predicate: Arc::new(predicate),
+        }
+    }
+
+    /// Creates a `Filter::Custom` from `predicate`
+    pub fn custom<F: Fn(&Log) -> bool + Send + Sync + 'static>(predicate: F) -> Self {
+        Self::Custom(Arc::new(predicate))
+    }
+
+    /// Returns true if `log` should be sent to the backend
+    #[must_use]
+    pub fn matches(&self, log: &Log) -> bool {
+        match self {
+            Self::MinimumLevel(level) => log.level >= *level,
+            Self::Process(process) => &log.process == process,
+            Self::Event(code) => log.event.as_ref().is_some_and(|event| &event.code == code),
+            Self::Payload { key, predicate } => {
+                log.payload.get(key).is_some_and(|value| predicate(value))
+            }
+            Self::Custom(predicate) => predicate(log),
+        }
+    }
+}
+
+impl From<Level> for Filter {
+    fn from(level: Level) -> Self {
+        Self::MinimumLevel(level)
+    }
+}
+
+impl Debug for Filter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MinimumLevel(level) => f.debug_tuple("MinimumLevel").field(level).finish(),
+            Self::Process(process) => f.debug_tuple("Process").field(process).finish(),
+            Self::Event(code) => f.debug_tuple("Event").field(code).finish(),
+            Self::Payload { key, .. } => f.debug_struct("Payload").field("key", key).finish(),
+            Self::Custom(_) => f.debug_tuple("Custom").finish(),
+        }
+    }
+}
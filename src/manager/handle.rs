@@ -0,0 +1,370 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::Duration,
+};
+
+use flume::{Receiver, Sender, TrySendError};
+
+use super::{worker::WorkerStatus, BackendLag, BackendOptions, LevelCounts, ManagerStats};
+use crate::{backend::Backend, Level, Log, LogError};
+
+/// Controls what happens when an entry is submitted to a `Manager` whose queue
+/// is full
+#[derive(Default, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Overflow {
+    /// Wait until there is room in the queue.
+    ///
+    /// `Log::submit` and the logging macros block the submitting thread. In
+    /// async code, this also blocks the other tasks of the executor thread,
+    /// and deadlocks if the manager's tasks can only execute on that thread.
+    /// Async code should use `Log::submit_async`, which waits without
+    /// blocking the thread.
+    #[default]
+    Block,
+    /// Drop the entry being submitted
+    DropNewest,
+    /// Drop the oldest entry in the queue to make room for the entry being
+    /// submitted
+    DropOldest,
+    /// Drop the entry being submitted if its level is below the provided
+    /// level, otherwise wait until there is room in the queue, as with
+    /// `Overflow::Block`
+    DropBelow(Level),
+}
+
+/// A message sent from a `ManagerHandle` to a running `Manager`
+#[derive(Debug)]
+pub enum Command {
+    /// Handle `request` once every entry up to and including sequence number
+    /// `through` has been dispatched
+    Sequenced {
+        through: u64,
+        request: Request,
+    },
+    Shutdown(Sender<()>),
+}
+
+/// A request that is handled in sequence with submitted entries
+#[derive(Debug)]
+pub enum Request {
+    Flush(Sender<()>),
+    Attach {
+        name: String,
+        backend: Box<dyn Backend>,
+        options: BackendOptions,
+        replace: bool,
+        reply: Sender<bool>,
+    },
+    Detach {
+        name: String,
+        reply: Sender<bool>,
+    },
+}
+
+/// Counters shared between a `Manager` and its handles
+#[derive(Default, Debug)]
+pub struct Shared {
+    /// The number of entries accepted into the queue
+    pub accepted: AtomicU64,
+    /// The number of accepted entries that were evicted from the queue before
+    /// the manager received them
+    pub evicted: AtomicU64,
+    /// The number of entries dropped since the last time drops were reported
+    pub dropped: AtomicU64,
+    /// The total number of entries dropped
+    pub dropped_total: AtomicU64,
+    /// The number of entries submitted at each level, indexed by `Level`
+    pub submitted: [AtomicU64; 5],
+    /// The lag tracking for each backend
+    pub workers: RwLock<Vec<Arc<WorkerStatus>>>,
+}
+
+impl Shared {
+    fn record_dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::SeqCst);
+        self.dropped_total.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Returns a snapshot of the manager's activity
+    pub fn stats(&self, queue_depth: usize) -> ManagerStats {
+        let submitted = |level: Level| self.submitted[level as usize].load(Ordering::SeqCst);
+        ManagerStats {
+            submitted: LevelCounts {
+                trace: submitted(Level::Trace),
+                debug: submitted(Level::Debug),
+                info: submitted(Level::Info),
+                warning: submitted(Level::Warning),
+                error: submitted(Level::Error),
+            },
+            dropped: self.dropped_total.load(Ordering::SeqCst),
+            queue_depth,
+            backends: self
+                .workers
+                .read()
+                .unwrap()
+                .iter()
+                .map(|status| status.stats())
+                .collect(),
+        }
+    }
+}
+
+/// A handle to a launched `Manager`. This is passed in during creation of a
+/// `Configuration`, and can be used to wait for submitted entries to be
+/// processed.
+#[derive(Clone, Debug)]
+pub struct ManagerHandle {
+    pub(super) entries: Sender<Arc<Log>>,
+    /// Only present when using `Overflow::DropOldest`
+    pub(super) evictor: Option<Receiver<Arc<Log>>>,
+    pub(super) commands: Sender<Command>,
+    pub(super) overflow: Overflow,
+    pub(super) shared: Arc<Shared>,
+}
+
+impl ManagerHandle {
+    pub(crate) fn send(&self, log: Arc<Log>) -> Result<(), LogError> {
+        self.shared.submitted[log.level as usize].fetch_add(1, Ordering::SeqCst);
+        let result = if self.waits_for_room(&log) {
+            self.entries
+                .send(log)
+                .map_err(|_| TrySendError::Disconnected(()))
+        } else {
+            self.try_send(log)
+        };
+        self.record_sent(result)
+    }
+
+    /// The equivalent of `send()` for async code. When the queue is full and
+    /// the `Overflow` strategy waits for room, this waits without blocking
+    /// the current thread.
+    pub(crate) async fn send_async(&self, log: Arc<Log>) -> Result<(), LogError> {
+        self.shared.submitted[log.level as usize].fetch_add(1, Ordering::SeqCst);
+        let result = if self.waits_for_room(&log) {
+            self.entries
+                .send_async(log)
+                .await
+                .map_err(|_| TrySendError::Disconnected(()))
+        } else {
+            self.try_send(log)
+        };
+        self.record_sent(result)
+    }
+
+    /// Returns whether submitting `log` waits for room in a full queue
+    fn waits_for_room(&self, log: &Log) -> bool {
+        match self.overflow {
+            Overflow::Block => true,
+            Overflow::DropBelow(level) => log.level >= level,
+            Overflow::DropNewest | Overflow::DropOldest => false,
+        }
+    }
+
+    /// Queues `log` without waiting, making room for it if the `Overflow`
+    /// strategy is `DropOldest`
+    fn try_send(&self, log: Arc<Log>) -> Result<(), TrySendError<()>> {
+        let Some(evictor) = &self.evictor else {
+            return self
+                .entries
+                .try_send(log)
+                .map_err(|error| discard_entry(&error));
+        };
+
+        let mut log = log;
+        loop {
+            match self.entries.try_send(log) {
+                Err(TrySendError::Full(rejected)) => {
+                    // The eviction must be counted before the new entry is
+                    // queued, so that the manager observes it no later than
+                    // the entry that replaced it.
+                    if evictor.try_recv().is_ok() {
+                        self.shared.evicted.fetch_add(1, Ordering::SeqCst);
+                        self.shared.record_dropped();
+                    }
+                    log = rejected;
+                }
+                other => break other.map_err(|error| discard_entry(&error)),
+            }
+        }
+    }
+
+    fn record_sent(&self, result: Result<(), TrySendError<()>>) -> Result<(), LogError> {
+        match result {
+            Ok(()) => {
+                self.shared.accepted.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            }
+            Err(TrySendError::Full(())) => {
+                self.shared.record_dropped();
+                Err(LogError::QueueFull)
+            }
+            Err(TrySendError::Disconnected(())) => Err(LogError::ManagerClosed),
+        }
+    }
+
+    /// Returns how far behind each backend is
+    ///
+    /// # Panics
+    ///
+    /// Panics if the manager panicked while changing its backends.
+    #[must_use]
+    pub fn lag(&self) -> Vec<BackendLag> {
+        self.shared
+            .workers
+            .read()
+            .unwrap()
+            .iter()
+            .map(|status| status.lag())
+            .collect()
+    }
+
+    /// Returns a snapshot of the manager's activity
+    #[must_use]
+    pub fn stats(&self) -> ManagerStats {
+        self.shared.stats(self.entries.len())
+    }
+
+    /// Waits until every entry submitted before this call has been processed
+    /// by every backend. Returns immediately if the manager is no longer
+    /// running.
+    pub async fn flush(&self) {
+        self.sequenced(Request::Flush).await;
+    }
+
+    /// The equivalent of `flush()` for code that cannot await, such as a
+    /// panic hook. Blocks the current thread until every entry submitted
+    /// before this call has been processed by every backend, or until
+    /// `timeout` has elapsed.
+    ///
+    /// Returns true if the entries were processed within `timeout`. If the
+    /// manager's tasks can only execute on the current thread, this waits for
+    /// the entire `timeout`.
+    #[must_use]
+    pub fn flush_blocking(&self, timeout: Duration) -> bool {
+        let through = self.shared.accepted.load(Ordering::SeqCst);
+        let (sender, receiver) = flume::bounded(1);
+        let command = Command::Sequenced {
+            through,
+            request: Request::Flush(sender),
+        };
+        self.commands.send(command).is_ok() && receiver.recv_timeout(timeout).is_ok()
+    }
+
+    /// Attaches `backend` to the running manager. The backend receives every
+    /// entry submitted after this call.
+    ///
+    /// Returns false if a backend named `name` is already attached or if the
+    /// manager is no longer running.
+    pub async fn add_backend<N: Into<String>, B: Backend + 'static>(
+        &self,
+        name: N,
+        backend: B,
+    ) -> bool {
+        self.add_backend_with_options(name, backend, BackendOptions::default())
+            .await
+    }
+
+    /// The equivalent of `add_backend()`, attaching `backend` with the
+    /// provided `options`
+    pub async fn add_backend_with_options<N: Into<String>, B: Backend + 'static>(
+        &self,
+        name: N,
+        backend: B,
+        options: BackendOptions,
+    ) -> bool {
+        self.attach(name.into(), Box::new(backend), options, false)
+            .await
+    }
+
+    /// Replaces the backend named `name` with `backend`. Entries submitted
+    /// before this call are processed by the previous backend, which is shut
+    /// down once it has processed them. Entries submitted after this call are
+    /// processed by `backend`.
+    ///
+    /// Returns false if no backend named `name` is attached or if the manager
+    /// is no longer running.
+    pub async fn replace_backend<N: Into<String>, B: Backend + 'static>(
+        &self,
+        name: N,
+        backend: B,
+    ) -> bool {
+        self.replace_backend_with_options(name, backend, BackendOptions::default())
+            .await
+    }
+
+    /// The equivalent of `replace_backend()`, attaching `backend` with the
+    /// provided `options`
+    pub async fn replace_backend_with_options<N: Into<String>, B: Backend + 'static>(
+        &self,
+        name: N,
+        backend: B,
+        options: BackendOptions,
+    ) -> bool {
+        self.attach(name.into(), Box::new(backend), options, true)
+            .await
+    }
+
+    /// Detaches the backend named `name`. The backend is shut down once it
+    /// has processed every entry submitted before this call.
+    ///
+    /// Returns false if no backend named `name` is attached or if the manager
+    /// is no longer running.
+    pub async fn remove_backend(&self, name: &str) -> bool {
+        let name = name.to_string();
+        self.sequenced(|reply| Request::Detach { name, reply })
+            .await
+            .unwrap_or(false)
+    }
+
+    /// Processes every entry already submitted, finalizes each backend using
+    /// `Backend::shutdown`, and stops the manager. Entries submitted after
+    /// the manager has stopped will not be delivered.
+    pub async fn shutdown(&self) {
+        self.request(Command::Shutdown).await;
+    }
+
+    async fn attach(
+        &self,
+        name: String,
+        backend: Box<dyn Backend>,
+        options: BackendOptions,
+        replace: bool,
+    ) -> bool {
+        self.sequenced(|reply| Request::Attach {
+            name,
+            backend,
+            options,
+            replace,
+            reply,
+        })
+        .await
+        .unwrap_or(false)
+    }
+
+    async fn sequenced<T, F: FnOnce(Sender<T>) -> Request>(&self, request: F) -> Option<T> {
+        let through = self.shared.accepted.load(Ordering::SeqCst);
+        self.request(|reply| Command::Sequenced {
+            through,
+            request: request(reply),
+        })
+        .await
+    }
+
+    /// Sends a command and waits for its reply. Returns `None` if the manager
+    /// stopped before replying.
+    async fn request<T, F: FnOnce(Sender<T>) -> Command>(&self, command: F) -> Option<T> {
+        let (sender, receiver) = flume::bounded(1);
+        self.commands.send_async(command(sender)).await.ok()?;
+        receiver.recv_async().await.ok()
+    }
+}
+
+const fn discard_entry(error: &TrySendError<Arc<Log>>) -> TrySendError<()> {
+    match error {
+        TrySendError::Full(_) => TrySendError::Full(()),
+        TrySendError::Disconnected(_) => TrySendError::Disconnected(()),
+    }
+}
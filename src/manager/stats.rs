@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+use serde::{Serialize, Serializer};
+
+use crate::Level;
+
+/// A snapshot of the activity of a `Manager`
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct ManagerStats {
+    /// The number of entries submitted to the manager, including entries that
+    /// were later dropped
+    pub submitted: LevelCounts,
+    /// The number of submitted entries that were dropped because the queue
+    /// was full
+    pub dropped: u64,
+    /// The number of entries waiting in the manager's queue
+    pub queue_depth: usize,
+    /// The statistics of each attached backend, in the order they were
+    /// attached
+    pub backends: Vec<BackendStats>,
+}
+
+/// The number of entries submitted at each `Level`
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct LevelCounts {
+    /// The number of `Level::Trace` entries
+    pub trace: u64,
+    /// The number of `Level::Debug` entries
+    pub debug: u64,
+    /// The number of `Level::Info` entries
+    pub info: u64,
+    /// The number of `Level::Warning` entries
+    pub warning: u64,
+    /// The number of `Level::Error` entries
+    pub error: u64,
+}
+
+impl LevelCounts {
+    /// Returns the count for `level`
+    #[must_use]
+    pub const fn get(&self, level: Level) -> u64 {
+        match level {
+            Level::Trace => self.trace,
+            Level::Debug => self.debug,
+            Level::Info => self.info,
+            Level::Warning => self.warning,
+            Level::Error => self.error,
+        }
+    }
+
+    /// Returns the sum of the counts of every level
+    #[must_use]
+    pub const fn total(&self) -> u64 {
+        self.trace + self.debug + self.info + self.warning + self.error
+    }
+}
+
+/// The activity of a backend attached to a `Manager`
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct BackendStats {
+    /// The index of the backend, in the order it was attached
+    pub backend: usize,
+    /// The name of the backend, if it was given one
+    pub name: Option<String>,
+    /// The number of entries the backend processed successfully
+    pub succeeded: u64,
+    /// The number of entries the backend failed to process, including entries
+    /// skipped while the backend was disabled
+    pub failed: u64,
+    /// The number of entries waiting to be processed by the backend
+    pub pending: usize,
+    /// The time between the creation of the most recently processed entry and
+    /// the backend finishing processing it
+    #[serde(rename = "delay_micros", serialize_with = "serialize_micros")]
+    pub delay: Duration,
+}
+
+fn serialize_micros<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_micros() as u64)
+}
@@ -0,0 +1,358 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use chrono::Utc;
+use flume::{Receiver, Sender, TryRecvError};
+use futures::FutureExt;
+use futures_timer::Delay;
+
+use super::{
+    failure::FailureCallback, BackendFailure, BackendOptions, BackendStats, FailurePolicy, Filter,
+};
+use crate::{
+    backend::{Backend, BatchError},
+    Log,
+};
+
+/// A message sent from a `Manager` to one of its backend workers
+#[derive(Debug)]
+pub enum WorkerMessage {
+    Log(Arc<Log>),
+    Flush(Sender<()>),
+    Shutdown(Sender<()>),
+}
+
+/// How far behind a backend attached to a `Manager` is
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BackendLag {
+    /// The index of the backend, in the order it was attached
+    pub backend: usize,
+    /// The name of the backend, if it was given one
+    pub name: Option<String>,
+    /// The number of entries waiting to be processed by the backend
+    pub pending: usize,
+    /// The time between the creation of the most recently processed entry and
+    /// the backend finishing processing it
+    pub delay: Duration,
+}
+
+/// Lag and activity tracking shared between a worker and the `Manager`
+#[derive(Debug)]
+pub struct WorkerStatus {
+    backend: usize,
+    name: Option<String>,
+    pending: AtomicUsize,
+    delay_micros: AtomicU64,
+    succeeded: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl WorkerStatus {
+    pub fn new(backend: usize, name: Option<String>) -> Self {
+        Self {
+            backend,
+            name,
+            pending: AtomicUsize::default(),
+            delay_micros: AtomicU64::default(),
+            succeeded: AtomicU64::default(),
+            failed: AtomicU64::default(),
+        }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn queued(&self) {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn processed(&self, log: &Log) {
+        let delay = Utc::now()
+            .signed_duration_since(log.timestamp)
+            .to_std()
+            .unwrap_or_default();
+        self.delay_micros
+            .store(delay.as_micros() as u64, Ordering::SeqCst);
+        self.pending.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    fn delay(&self) -> Duration {
+        Duration::from_micros(self.delay_micros.load(Ordering::SeqCst))
+    }
+
+    pub fn lag(&self) -> BackendLag {
+        BackendLag {
+            backend: self.backend,
+            name: self.name.clone(),
+            pending: self.pending.load(Ordering::SeqCst),
+            delay: self.delay(),
+        }
+    }
+
+    pub fn stats(&self) -> BackendStats {
+        BackendStats {
+            backend: self.backend,
+            name: self.name.clone(),
+            succeeded: self.succeeded.load(Ordering::SeqCst),
+            failed: self.failed.load(Ordering::SeqCst),
+            pending: self.pending.load(Ordering::SeqCst),
+            delay: self.delay(),
+        }
+    }
+}
+
+/// Controls how many queued entries a worker passes to its backend at once
+#[derive(Clone, Copy, Debug)]
+pub struct Batching {
+    /// The maximum number of entries in a batch
+    pub max_size: usize,
+    /// How long to wait for more entries to arrive before processing a batch
+    /// that isn't full
+    pub linger: Duration,
+}
+
+impl Default for Batching {
+    fn default() -> Self {
+        Self {
+            max_size: 64,
+            linger: Duration::default(),
+        }
+    }
+}
+
+/// A backend attached to a `Manager`. Once the `Manager` is launched, each
+/// worker processes entries from its own queue in its own task.
+#[derive(Debug)]
+pub struct Worker {
+    backend: Box<dyn Backend>,
+    options: BackendOptions,
+    consecutive_failures: u32,
+    disabled: bool,
+    status: Arc<WorkerStatus>,
+}
+
+impl Worker {
+    pub fn new(index: usize, backend: Box<dyn Backend>, options: BackendOptions) -> Self {
+        let status = Arc::new(WorkerStatus::new(index, options.name.clone()));
+        Self {
+            backend,
+            options,
+            consecutive_failures: 0,
+            disabled: false,
+            status,
+        }
+    }
+
+    pub const fn take_filter(&mut self) -> Option<Filter> {
+        self.options.filter.take()
+    }
+
+    pub fn status(&self) -> Arc<WorkerStatus> {
+        self.status.clone()
+    }
+
+    pub async fn run(
+        mut self,
+        queue: Receiver<WorkerMessage>,
+        batching: Batching,
+        failure_callback: FailureCallback,
+    ) {
+        self.lifecycle(Lifecycle::Init, &failure_callback).await;
+
+        let mut next_message = None;
+        loop {
+            let message = match next_message.take() {
+                Some(message) => message,
+                None => match queue.recv_async().await {
+                    Ok(message) => message,
+                    Err(_) => break,
+                },
+            };
+
+            match message {
+                WorkerMessage::Log(log) => {
+                    let mut batch = vec![log];
+                    next_message = fill_batch(&queue, &mut batch, batching).await;
+                    self.process_batch(batch.clone(), &failure_callback).await;
+                    for log in &batch {
+                        self.status.processed(log);
+                    }
+
+                    if next_message.is_none() && queue.is_empty() && !self.disabled {
+                        self.lifecycle(Lifecycle::Flush, &failure_callback).await;
+                    }
+                }
+                WorkerMessage::Flush(reply) => {
+                    self.lifecycle(Lifecycle::Flush, &failure_callback).await;
+                    let _ = reply.send(());
+                }
+                WorkerMessage::Shutdown(reply) => {
+                    self.lifecycle(Lifecycle::Flush, &failure_callback).await;
+                    self.lifecycle(Lifecycle::Shutdown, &failure_callback).await;
+                    let _ = reply.send(());
+                    break;
+                }
+            }
+        }
+    }
+
+    async fn process_batch(&mut self, batch: Vec<Arc<Log>>, failure_callback: &FailureCallback) {
+        let mut remaining = &batch[..];
+        while !remaining.is_empty() {
+            if self.disabled {
+                self.status
+                    .failed
+                    .fetch_add(remaining.len() as u64, Ordering::SeqCst);
+                return;
+            }
+
+            let Err(BatchError { processed, error }) = self.try_process(remaining).await else {
+                self.record_succeeded(remaining.len());
+                return;
+            };
+
+            // Each entry that fails is reported on its own, and the entries
+            // after it are still processed.
+            let processed = processed.min(remaining.len() - 1);
+            self.record_succeeded(processed);
+            let failed = remaining[processed].clone();
+            remaining = &remaining[processed + 1..];
+
+            self.consecutive_failures += 1;
+            self.status.failed.fetch_add(1, Ordering::SeqCst);
+            if let FailurePolicy::DisableAfter(failures) = &self.options.failure_policy {
+                self.disabled = self.consecutive_failures >= *failures;
+            }
+            failure_callback.report(&BackendFailure {
+                backend: self.status.backend,
+                name: self.status.name.clone(),
+                entries: vec![failed.clone()],
+                error,
+                disabled: self.disabled,
+            });
+
+            if let FailurePolicy::Fallback(fallback) = &mut self.options.failure_policy {
+                if let Err(failure) = fallback.process_batch(std::slice::from_ref(&failed)).await {
+                    failure_callback.report(&BackendFailure {
+                        backend: self.status.backend,
+                        name: self.status.name.clone(),
+                        entries: vec![failed],
+                        error: failure.error.context("fallback backend failed"),
+                        disabled: self.disabled,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Passes `logs` to the backend, retrying from the failing entry if the
+    /// failure policy allows it. The returned error's `processed` count is
+    /// relative to the start of `logs`.
+    async fn try_process(&mut self, logs: &[Arc<Log>]) -> Result<(), BatchError> {
+        let mut result = self.backend.process_batch(logs).await;
+        let mut offset = 0;
+        if let FailurePolicy::Retry { attempts, backoff } = self.options.failure_policy {
+            let mut backoff = backoff;
+            for _ in 0..attempts {
+                let Err(failure) = &result else {
+                    break;
+                };
+                // Entries processed before the failure aren't sent again.
+                offset = (offset + failure.processed).min(logs.len() - 1);
+                Delay::new(backoff).await;
+                backoff *= 2;
+                result = self.backend.process_batch(&logs[offset..]).await;
+            }
+        }
+
+        result.map_err(|mut failure| {
+            failure.processed += offset;
+            failure
+        })
+    }
+
+    fn record_succeeded(&mut self, entries: usize) {
+        if entries > 0 {
+            self.consecutive_failures = 0;
+            self.status
+                .succeeded
+                .fetch_add(entries as u64, Ordering::SeqCst);
+        }
+    }
+
+    /// Invokes a lifecycle hook on the backend and its fallback, if present
+    async fn lifecycle(&mut self, stage: Lifecycle, failure_callback: &FailureCallback) {
+        let mut result = stage.invoke(self.backend.as_mut()).await;
+        if let FailurePolicy::Fallback(fallback) = &mut self.options.failure_policy {
+            result = result.and(stage.invoke(fallback.as_mut()).await);
+        }
+
+        if let Err(error) = result {
+            failure_callback.report(&BackendFailure {
+                backend: self.status.backend,
+                name: self.status.name.clone(),
+                entries: Vec::new(),
+                error,
+                disabled: self.disabled,
+            });
+        }
+    }
+}
+
+/// The lifecycle hooks of a `Backend`
+#[derive(Clone, Copy, Debug)]
+enum Lifecycle {
+    Init,
+    Flush,
+    Shutdown,
+}
+
+impl Lifecycle {
+    async fn invoke(self, backend: &mut dyn Backend) -> anyhow::Result<()> {
+        match self {
+            Self::Init => backend.init().await,
+            Self::Flush => backend.flush().await,
+            Self::Shutdown => backend.shutdown().await,
+        }
+    }
+}
+
+/// Adds entries that are already queued (or that arrive within
+/// `batching.linger`) to `batch`. If a message other than an entry is
+/// encountered, it is returned so that it can be handled once the batch has
+/// been processed.
+async fn fill_batch(
+    queue: &Receiver<WorkerMessage>,
+    batch: &mut Vec<Arc<Log>>,
+    batching: Batching,
+) -> Option<WorkerMessage> {
+    let mut linger = Delay::new(batching.linger).fuse();
+    while batch.len() < batching.max_size {
+        let message = match queue.try_recv() {
+            Ok(message) => message,
+            Err(TryRecvError::Empty) if batching.linger > Duration::default() => {
+                futures::select_biased! {
+                    message = queue.recv_async() => match message {
+                        Ok(message) => message,
+                        Err(_) => return None,
+                    },
+                    () = linger => return None,
+                }
+            }
+            Err(_) => return None,
+        };
+
+        match message {
+            WorkerMessage::Log(log) => batch.push(log),
+            other => return Some(other),
+        }
+    }
+
+    None
+}
@@ -0,0 +1,177 @@
+use std::{
+    backtrace::{Backtrace, BacktraceStatus},
+    borrow::Cow,
+    time::Duration,
+};
+
+use crate::{Configuration, Level, Location, Log, LogError};
+
+/// Installs a `PanicHook` with its default settings
+pub fn install_panic_hook() {
+    PanicHook::default().install();
+}
+
+/// A panic hook that submits a `Level::Error` entry for each panic through
+/// the current `Configuration`
+///
+/// The entry's message is the panic message, and its payload contains the
+/// name of the panicking `thread` and, if one was captured, the `backtrace`.
+/// After submitting the entry, the hook waits for the manager to flush before
+/// invoking the previously installed hook.
+#[derive(Clone, Copy, Debug)]
+pub struct PanicHook {
+    /// The longest the hook waits for the manager to flush
+    pub flush_timeout: Duration,
+    /// If true, a backtrace is always captured. Otherwise, a backtrace is
+    /// only captured if enabled by the `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE`
+    /// environment variables.
+    pub force_backtrace: bool,
+}
+
+impl Default for PanicHook {
+    fn default() -> Self {
+        Self {
+            flush_timeout: Duration::from_secs(1),
+            force_backtrace: false,
+        }
+    }
+}
+
+impl PanicHook {
+    /// Builder-style method to set `flush_timeout`
+    #[must_use]
+    pub const fn with_flush_timeout(mut self, flush_timeout: Duration) -> Self {
+        self.flush_timeout = flush_timeout;
+        self
+    }
+
+    /// Builder-style method to set `force_backtrace`
+    #[must_use]
+    pub const fn with_force_backtrace(mut self, force_backtrace: bool) -> Self {
+        self.force_backtrace = force_backtrace;
+        self
+    }
+
+    /// Installs this hook, chaining to the currently installed hook
+    pub fn install(self) {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            self.report(info);
+            previous(info);
+        }));
+    }
+
+    fn report(self, info: &std::panic::PanicHookInfo<'_>) {
+        let Some(config) = Configuration::current() else {
+            return;
+        };
+
+        // Failing to log must not cause a panic while panicking, so errors are
+        // ignored rather than handled using `OnFailure`
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("Box<dyn Any>");
+        if let Ok(log) = self.entry(message, info.location()) {
+            if log.try_submit().is_ok() {
+                let _ = config.destination.flush_blocking(self.flush_timeout);
+            }
+        }
+    }
+
+    /// Creates the entry reported for a panic with `message` at `location`
+    /// on the current thread
+    fn entry(
+        self,
+        message: &str,
+        location: Option<&std::panic::Location<'_>>,
+    ) -> Result<Log, LogError> {
+        let mut log = Log::try_new(Level::Error, message)?;
+        log.location = location.map(|location| Location {
+            file: Cow::Owned(location.file().to_string()),
+            line: location.line(),
+            column: location.column(),
+            module_path: Cow::Borrowed(""),
+        });
+
+        let thread = std::thread::current();
+        let _ = log.add("thread", thread.name().unwrap_or("<unnamed>"));
+        let backtrace = if self.force_backtrace {
+            Backtrace::force_capture()
+        } else {
+            Backtrace::capture()
+        };
+        if backtrace.status() == BacktraceStatus::Captured {
+            let _ = log.add("backtrace", backtrace.to_string());
+        }
+        Ok(log)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::{block_on, ThreadPool};
+
+    use super::*;
+    use crate::{backend::Memory, Manager};
+
+    #[test]
+    fn entry_test() {
+        let handle = Manager::default().launch(|_| {});
+        let location = std::panic::Location::caller();
+        let log = block_on(Configuration::named("entry_test", handle).run(async {
+            PanicHook::default()
+                .with_force_backtrace(true)
+                .entry("something went wrong", Some(location))
+        }))
+        .unwrap();
+
+        assert_eq!(log.level, Level::Error);
+        assert_eq!(log.process, "entry_test");
+        assert_eq!(log.message, "something went wrong");
+        assert_eq!(
+            log.payload["thread"],
+            std::thread::current().name().unwrap_or("<unnamed>")
+        );
+        assert!(log.payload["backtrace"].is_string());
+        let logged_location = log.location.unwrap();
+        assert_eq!(logged_location.file, location.file());
+        assert_eq!(logged_location.line, location.line());
+    }
+
+    #[test]
+    fn panic_hook_test() {
+        let pool = ThreadPool::new().unwrap();
+        let memory = Memory::new(10);
+        let entries = memory.entries.clone();
+        let handle = Manager::default()
+            .with_backend(memory)
+            .launch(move |task| pool.spawn_ok(task));
+
+        // The hook is process-wide, so the original hook is restored as soon
+        // as the thread has panicked
+        let original = std::panic::take_hook();
+        PanicHook::default().install();
+        let result = std::thread::Builder::new()
+            .name(String::from("panicking"))
+            .spawn(move || {
+                block_on(Configuration::named("panic_hook_test", handle).run(async {
+                    panic!("something went wrong: {}", 42);
+                }));
+            })
+            .unwrap()
+            .join();
+        std::panic::set_hook(original);
+        assert!(result.is_err());
+
+        // The hook flushed the manager before the thread finished panicking
+        let entries = block_on(entries.lock()).clone();
+        assert_eq!(entries.len(), 1);
+        let log = &entries[0];
+        assert_eq!(log.message, "something went wrong: 42");
+        assert_eq!(log.payload["thread"], "panicking");
+        assert_eq!(log.location.as_ref().unwrap().file, file!());
+    }
+}
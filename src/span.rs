@@ -0,0 +1,347 @@
+use std::{
+    fmt::Display,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use futures::Future;
+use serde::Serialize;
+
+use crate::{
+    context::{extend_fields, Fields, Scoped, TaskContext},
+    Configuration, Level, Log,
+};
+
+/// The id of the next span to be entered
+static NEXT_SPAN_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Creates a `Span` named `name`
+pub fn span<N: Into<String>>(name: N) -> Span {
+    Span {
+        name: name.into(),
+        level: Level::Info,
+        fields: Fields::new(),
+    }
+}
+
+/// A timed unit of work. A span submits an entry when it is entered, and
+/// another containing the elapsed duration when it exits.
+///
+/// Both entries carry the span's `span_id` and, if it was entered while
+/// another span was running, the `parent_span_id` in their payload. Entries
+/// created from within `Span::run()`, `Span::try_run()` or while the guard
+/// returned from `Span::enter()` is alive also carry the `span_id`.
+#[derive(Debug)]
+#[must_use = "spans do nothing unless they are entered or run"]
+pub struct Span {
+    name: String,
+    level: Level,
+    fields: Fields,
+}
+
+impl Span {
+    /// Builder-style method to set the level of the span's entries. Failures
+    /// are always submitted with `Level::Error`. By default, `Level::Info` is
+    /// used.
+    pub const fn with_level(mut self, level: Level) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Builder-style method to add `fields` to the payload of the span's
+    /// entries
+    pub fn fields<I, K, V>(mut self, fields: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Serialize,
+    {
+        extend_fields(&mut self.fields, fields);
+        self
+    }
+
+    /// Enters the span, returning a guard that exits the span when dropped.
+    ///
+    /// Until the guard is dropped, entries created and spans entered on the
+    /// current thread are associated with this span. Because of this, the
+    /// guard must not be held across an `.await`. In async code, use `run()`,
+    /// `try_run()` or `SpanGuard::scope()` instead.
+    pub fn enter(self) -> SpanGuard {
+        let mut guard = self.start();
+        let previous = TaskContext::current();
+        TaskContext::replace(previous.with_layer(&guard.layer()));
+        guard.restore = Some(previous);
+        guard
+    }
+
+    /// Submits the span's start entry, returning a guard that exits the span
+    /// when dropped without making it the current thread's active span
+    fn start(self) -> SpanGuard {
+        let context = TaskContext::current();
+        let guard = SpanGuard {
+            id: NEXT_SPAN_ID.fetch_add(1, Ordering::SeqCst),
+            parent_id: context.span,
+            span: self,
+            started: Instant::now(),
+            error: None,
+            restore: None,
+        };
+        guard.submit(
+            guard.span.level,
+            format!("{} started", guard.span.name),
+            None,
+        );
+        guard
+    }
+
+    /// Executes `future` within the span
+    pub async fn run<F: Future>(self, future: F) -> F::Output {
+        let guard = self.start();
+        guard.scope(future).await
+    }
+
+    /// Executes `future` within the span. If `future` returns an error, the
+    /// span's exit entry is submitted with `Level::Error` and contains the
+    /// error.
+    pub async fn try_run<T, E: Display, F: Future<Output = Result<T, E>>>(
+        self,
+        future: F,
+    ) -> Result<T, E> {
+        let mut guard = self.start();
+        let result = guard.scope(future).await;
+        if let Err(error) = &result {
+            guard.fail(error);
+        }
+        result
+    }
+}
+
+/// An entered `Span`, which exits when dropped
+#[derive(Debug)]
+#[must_use = "the span exits when the guard is dropped"]
+pub struct SpanGuard {
+    id: u64,
+    parent_id: Option<u64>,
+    span: Span,
+    started: Instant,
+    error: Option<String>,
+    /// The context to reinstall when the guard is dropped, if the span was
+    /// made the current thread's active span by `Span::enter()`
+    restore: Option<TaskContext>,
+}
+
+impl SpanGuard {
+    /// Returns the id of the span
+    #[must_use]
+    pub const fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the id of the span that was running when this span was
+    /// entered
+    #[must_use]
+    pub const fn parent_id(&self) -> Option<u64> {
+        self.parent_id
+    }
+
+    /// Records that the span failed with `error`. The span's exit entry is
+    /// submitted with `Level::Error` and contains the error.
+    pub fn fail<E: Display>(&mut self, error: E) {
+        self.error = Some(error.to_string());
+    }
+
+    /// Wraps `future` so that spans entered and entries created from within
+    /// it are associated with this span
+    pub fn scope<F: Future>(&self, future: F) -> Scoped<F> {
+        Scoped::layered(self.layer(), future)
+    }
+
+    /// Returns the context layer that associates entries and spans with this
+    /// span
+    fn layer(&self) -> TaskContext {
+        let mut fields = Fields::new();
+        fields.insert(String::from("span_id"), serde_json::Value::from(self.id));
+        TaskContext {
+            config: None,
+            fields: Some(Arc::new(fields)),
+            span: Some(self.id),
+        }
+    }
+
+    fn submit(&self, level: Level, message: String, elapsed: Option<u128>) {
+        if !Configuration::enabled(level) {
+            return;
+        }
+
+        let mut payload = self.span.fields.clone();
+        payload.insert(String::from("span"), self.span.name.clone().into());
+        payload.insert(String::from("span_id"), self.id.into());
+        if let Some(parent_id) = self.parent_id {
+            payload.insert(String::from("parent_span_id"), parent_id.into());
+        }
+        if let Some(elapsed) = elapsed {
+            payload.insert(String::from("elapsed_micros"), (elapsed as u64).into());
+        }
+        if let Some(error) = &self.error {
+            payload.insert(String::from("error"), error.clone().into());
+        }
+
+        Log::submit_or_handle(Log::try_new(level, message).map(|mut log| {
+            log.payload = serde_json::Value::Object(payload);
+            log
+        }));
+    }
+}
+
+impl Drop for SpanGuard {
+    fn drop(&mut self) {
+        let elapsed = self.started.elapsed();
+        let (level, message) = match &self.error {
+            Some(error) => (
+                Level::Error,
+                format!("{} failed after {:?}: {}", self.span.name, elapsed, error),
+            ),
+            None => (
+                self.span.level,
+                format!("{} finished in {:?}", self.span.name, elapsed),
+            ),
+        };
+        self.submit(level, message, Some(elapsed.as_micros()));
+
+        // Guards dropped out of order leave the context of the span that is
+        // still active in place.
+        if let Some(previous) = self.restore.take() {
+            if TaskContext::current().span == Some(self.id) {
+                TaskContext::replace(previous);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{backend::Memory, Manager};
+
+    #[tokio::test]
+    async fn span_test() {
+        let memory = Memory::new(20);
+        let entries = memory.entries.clone();
+        let handle = Manager::default().with_backend(memory).launch(|task| {
+            tokio::spawn(task);
+        });
+
+        Configuration::named("span_test", handle.clone())
+            .run(async {
+                span("request")
+                    .fields([("path", "/")])
+                    .run(async {
+                        Log::info("handling").submit();
+                        let result = span("db.query")
+                            .try_run(async { Err::<(), _>("connection lost") })
+                            .await;
+                        assert!(result.is_err());
+                    })
+                    .await;
+
+                let mut guard = span("manual").with_level(Level::Debug).enter();
+                assert_eq!(guard.parent_id(), None);
+                guard.fail("oops");
+            })
+            .await;
+        handle.flush().await;
+
+        let entries = entries.lock().await.clone();
+        let entries = entries.iter().rev().collect::<Vec<_>>();
+        let messages = entries
+            .iter()
+            .map(|log| {
+                (
+                    log.level,
+                    log.message.split(' ').take(2).collect::<Vec<_>>().join(" "),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                (Level::Info, String::from("request started")),
+                (Level::Info, String::from("handling")),
+                (Level::Info, String::from("db.query started")),
+                (Level::Error, String::from("db.query failed")),
+                (Level::Info, String::from("request finished")),
+                (Level::Debug, String::from("manual started")),
+                (Level::Error, String::from("manual failed")),
+            ]
+        );
+
+        let request_id = &entries[0].payload["span_id"];
+        assert_eq!(entries[0].payload["span"], "request");
+        assert_eq!(entries[0].payload["path"], "/");
+        assert!(entries[0].payload.get("parent_span_id").is_none());
+        assert!(entries[0].payload.get("elapsed_micros").is_none());
+        assert_eq!(&entries[1].payload["span_id"], request_id);
+        assert_eq!(&entries[2].payload["parent_span_id"], request_id);
+        assert_eq!(entries[3].payload["error"], "connection lost");
+        assert!(entries[3].payload["elapsed_micros"].is_u64());
+        assert_eq!(&entries[4].payload["span_id"], request_id);
+        assert_eq!(entries[4].payload["path"], "/");
+        assert!(entries[5].payload.get("parent_span_id").is_none());
+    }
+
+    #[tokio::test]
+    async fn nested_guards_test() {
+        let memory = Memory::new(20);
+        let entries = memory.entries.clone();
+        let handle = Manager::default().with_backend(memory).launch(|task| {
+            tokio::spawn(task);
+        });
+
+        Configuration::named("nested_guards_test", handle.clone())
+            .run(async {
+                let outer = span("outer").enter();
+                Log::info("between").submit();
+                let inner = span("inner").enter();
+                assert_eq!(inner.parent_id(), Some(outer.id()));
+                Log::info("inside").submit();
+                drop(inner);
+                Log::info("after inner").submit();
+                drop(outer);
+                Log::info("outside").submit();
+            })
+            .await;
+        handle.flush().await;
+
+        let entries = entries.lock().await.clone();
+        let span_ids = entries
+            .iter()
+            .rev()
+            .map(|log| {
+                (
+                    log.message.split(" in ").next().unwrap(),
+                    log.payload.get("span_id").cloned(),
+                    log.payload.get("parent_span_id").cloned(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let outer = span_ids[0].1.clone();
+        let inner = span_ids[2].1.clone();
+        assert_ne!(outer, inner);
+        assert_eq!(
+            span_ids,
+            vec![
+                ("outer started", outer.clone(), None),
+                ("between", outer.clone(), None),
+                ("inner started", inner.clone(), outer.clone()),
+                ("inside", inner.clone(), None),
+                ("inner finished", inner, outer.clone()),
+                ("after inner", outer.clone(), None),
+                ("outer finished", outer, None),
+                ("outside", None, None),
+            ]
+        );
+    }
+}
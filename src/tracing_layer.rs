@@ -0,0 +1,193 @@
+use std::{borrow::Cow, fmt::Debug};
+
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    Event, Subscriber,
+};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+use crate::{Configuration, Fields, Level, Location, Log};
+
+/// A `tracing_subscriber::Layer` that submits `tracing` events through the
+/// current `Configuration`
+///
+/// Each event's fields are added to the payload, except for `message`, which
+/// is used as the entry's message. The event's target is added to the
+/// payload as `target`, and the spans the event occurred within are added as
+/// `spans`, outermost first. Each span is an object containing its `name` and
+/// its fields.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TracingLayer;
+
+impl<S> Layer<S> for TracingLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut fields = SpanFields::default();
+            attrs.record(&mut FieldVisitor(&mut fields.0));
+            span.extensions_mut().insert(fields);
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(fields) = span.extensions_mut().get_mut::<SpanFields>() {
+                values.record(&mut FieldVisitor(&mut fields.0));
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let level = level(*metadata.level());
+        if !Configuration::enabled(level) {
+            return;
+        }
+
+        let mut payload = Fields::new();
+        event.record(&mut FieldVisitor(&mut payload));
+        let message = match payload.remove("message") {
+            Some(serde_json::Value::String(message)) => message,
+            Some(other) => other.to_string(),
+            None => String::new(),
+        };
+        payload.insert(String::from("target"), metadata.target().into());
+
+        if let Some(scope) = ctx.event_scope(event) {
+            let spans = scope
+                .from_root()
+                .map(|span| {
+                    let mut fields = span
+                        .extensions()
+                        .get::<SpanFields>()
+                        .map(|fields| fields.0.clone())
+                        .unwrap_or_default();
+                    fields.insert(String::from("name"), span.name().into());
+                    serde_json::Value::Object(fields)
+                })
+                .collect::<Vec<_>>();
+            payload.insert(String::from("spans"), spans.into());
+        }
+
+        Log::submit_or_handle(Log::try_new(level, message).map(|mut log| {
+            log.payload = serde_json::Value::Object(payload);
+            if let (Some(file), Some(line)) = (metadata.file(), metadata.line()) {
+                log.location = Some(Location {
+                    file: Cow::Borrowed(file),
+                    line,
+                    column: 0,
+                    module_path: Cow::Borrowed(metadata.module_path().unwrap_or_default()),
+                });
+            }
+            log
+        }));
+    }
+}
+
+/// The fields recorded for a span, stored in the span's extensions
+#[derive(Debug, Default)]
+struct SpanFields(Fields);
+
+/// Records the fields of an event or span into a payload
+struct FieldVisitor<'a>(&'a mut Fields);
+
+impl FieldVisitor<'_> {
+    fn insert<V: Into<serde_json::Value>>(&mut self, field: &Field, value: V) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+}
+
+impl Visit for FieldVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, value);
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, value);
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, value);
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, value);
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, value);
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        self.insert(field, value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.insert(field, format!("{value:?}"));
+    }
+}
+
+const fn level(level: tracing::Level) -> Level {
+    match level {
+        tracing::Level::ERROR => Level::Error,
+        tracing::Level::WARN => Level::Warning,
+        tracing::Level::INFO => Level::Info,
+        tracing::Level::DEBUG => Level::Debug,
+        tracing::Level::TRACE => Level::Trace,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+    use crate::{backend::Memory, Manager};
+
+    #[tokio::test]
+    async fn layer_test() {
+        let memory = Memory::new(10);
+        let entries = memory.entries.clone();
+        let handle = Manager::default().with_backend(memory).launch(|task| {
+            tokio::spawn(task);
+        });
+        let subscriber = tracing_subscriber::registry().with(TracingLayer);
+
+        Configuration::named("layer_test", handle.clone())
+            .with_minimum_level(Level::Info)
+            .run(async {
+                tracing::subscriber::with_default(subscriber, || {
+                    let span = tracing::info_span!("request", id = 7_u64);
+                    let _entered = span.enter();
+                    let inner = tracing::info_span!("db", table = "users");
+                    let _inner = inner.enter();
+                    tracing::warn!(target: "db", rows = 5_u64, "query took {}ms", 12);
+                    tracing::debug!("not delivered");
+                });
+            })
+            .await;
+        handle.flush().await;
+
+        let entries = entries.lock().await.clone();
+        assert_eq!(entries.len(), 1);
+        let log = &entries[0];
+        assert_eq!(log.level, Level::Warning);
+        assert_eq!(log.process, "layer_test");
+        assert_eq!(log.message, "query took 12ms");
+        assert_eq!(
+            log.payload,
+            serde_json::json!({
+                "target": "db",
+                "rows": 5,
+                "spans": [
+                    {"name": "request", "id": 7},
+                    {"name": "db", "table": "users"},
+                ],
+            })
+        );
+        assert_eq!(log.location.as_ref().unwrap().file, file!());
+    }
+}